@@ -0,0 +1,102 @@
+// `#[derive(ToBencode, FromBencode)]` for structs with named fields. Each field
+// maps to the dictionary key of the same name, or to the one given with
+// `#[bencode(rename = "...")]`. Option fields leave their key out when None and
+// are None when the key is missing.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Ident, LitStr};
+
+#[proc_macro_derive(ToBencode, attributes(bencode))]
+pub fn derive_to_bencode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_to_bencode(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+#[proc_macro_derive(FromBencode, attributes(bencode))]
+pub fn derive_from_bencode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_bencode(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand_to_bencode(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let mut fields = named_fields(input)?;
+    // Inserting in key order makes the encoding canonical, like the HashMap
+    // impl and ser::to_bytes
+    fields.sort_by(|(_, a), (_, b)| a.as_bytes().cmp(b.as_bytes()));
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let inserts = fields.iter().map(|(ident, key)| {
+        let key = Literal::byte_string(key.as_bytes());
+        quote! {
+            if let ::std::option::Option::Some(value) = ::domenec::convert::ToBencode::to_field(&self.#ident) {
+                dict.insert(::domenec::ByteString(#key.to_vec()), value);
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::domenec::convert::ToBencode for #name #ty_generics #where_clause {
+            fn to_bencode(&self) -> ::domenec::BEncodingType {
+                let mut dict = ::domenec::convert::Dict::new();
+                #(#inserts)*
+                ::domenec::BEncodingType::Dictionary(dict)
+            }
+        }
+    })
+}
+
+fn expand_from_bencode(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = named_fields(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let inits = fields.iter().map(|(ident, key)| {
+        let key = Literal::byte_string(key.as_bytes());
+        quote! {
+            #ident: ::domenec::convert::FromBencode::from_field(
+                #key,
+                dict.remove(&::domenec::ByteString(#key.to_vec())),
+            )?,
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::domenec::convert::FromBencode for #name #ty_generics #where_clause {
+            fn from_bencode(value: ::domenec::BEncodingType)
+                -> ::std::result::Result<Self, ::domenec::error::ConvertError> {
+                let mut dict = ::domenec::convert::into_dict(value)?;
+                ::std::result::Result::Ok(#name { #(#inits)* })
+            }
+        }
+    })
+}
+
+// Field identifiers with their dictionary key
+fn named_fields(input: &DeriveInput) -> syn::Result<Vec<(&Ident, String)>> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new_spanned(&input.ident, "only structs with named fields are supported")),
+        },
+        _ => return Err(Error::new_spanned(&input.ident, "only structs with named fields are supported")),
+    };
+
+    fields.iter()
+        .map(|field| {
+            let ident = field.ident.as_ref().expect("named field");
+            let mut key = ident.to_string();
+            for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("bencode")) {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        key = meta.value()?.parse::<LitStr>()?.value();
+                        Ok(())
+                    } else {
+                        Err(meta.error("unknown bencode attribute, expected `rename`"))
+                    }
+                })?;
+            }
+            Ok((ident, key))
+        })
+        .collect()
+}
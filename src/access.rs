@@ -0,0 +1,390 @@
+// Typed accessors, so callers don't have to match on BEncodingType for every
+// field they read. The `as_*` methods borrow and return None on a type
+// mismatch, the `try_into_*` ones consume the value and report what was found
+// instead.
+//
+// Values can be edited in place: `as_list_mut` and `as_dict_mut` expose the
+// containers, and `insert`, `remove`, `entry` and `push` are shortcuts that
+// fail with a TypeError when called on the wrong kind of value.
+//
+// From and TryFrom mirror the constructors and the `try_into_*` methods.
+//
+// Indexing with a key or a position, `value["info"]["name"]`, panics when the
+// entry is missing, like slices do. `get` and `get_mut` are the non-panicking
+// versions.
+
+use std::borrow::Cow;
+use std::ops::{Index, IndexMut};
+
+use linked_hash_map::{Entry, LinkedHashMap};
+
+use crate::bdecode::BEncodingType;
+use crate::bytestring::ByteString;
+use crate::convert::ToBencode;
+use crate::error::TypeError;
+use crate::index::PathSegment;
+
+type Result<T> = std::result::Result<T, TypeError>;
+
+impl BEncodingType {
+    // Name of the variant, as used in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            BEncodingType::Integer(_) => "integer",
+            BEncodingType::String(_) => "string",
+            BEncodingType::List(_) => "list",
+            BEncodingType::Dictionary(_) => "dictionary",
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            BEncodingType::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            BEncodingType::String(s) => Some(&s.0),
+            _ => None,
+        }
+    }
+
+    // The string with invalid UTF-8 replaced by U+FFFD
+    pub fn as_str_lossy(&self) -> Option<Cow<'_, str>> {
+        self.as_bytes().map(String::from_utf8_lossy)
+    }
+
+    pub fn as_list(&self) -> Option<&[BEncodingType]> {
+        match self {
+            BEncodingType::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&LinkedHashMap<ByteString, BEncodingType>> {
+        match self {
+            BEncodingType::Dictionary(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Option<&mut Vec<BEncodingType>> {
+        match self {
+            BEncodingType::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict_mut(&mut self) -> Option<&mut LinkedHashMap<ByteString, BEncodingType>> {
+        match self {
+            BEncodingType::Dictionary(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    // Sets `key` in a dictionary, returning the value it replaces
+    pub fn insert(&mut self, key: impl AsRef<[u8]>, value: impl ToBencode) -> Result<Option<BEncodingType>> {
+        Ok(self.dict_mut()?.insert(ByteString(key.as_ref().to_vec()), value.to_bencode()))
+    }
+
+    pub fn remove(&mut self, key: impl AsRef<[u8]>) -> Result<Option<BEncodingType>> {
+        Ok(self.dict_mut()?.remove(key.as_ref()))
+    }
+
+    pub fn entry(&mut self, key: impl AsRef<[u8]>) -> Result<Entry<'_, ByteString, BEncodingType>> {
+        Ok(self.dict_mut()?.entry(ByteString(key.as_ref().to_vec())))
+    }
+
+    // Appends to a list
+    pub fn push(&mut self, value: impl ToBencode) -> Result<()> {
+        let found = self.type_name();
+        let list = self.as_list_mut().ok_or(TypeError { expected: "list", found })?;
+        list.push(value.to_bencode());
+        Ok(())
+    }
+
+    fn dict_mut(&mut self) -> Result<&mut LinkedHashMap<ByteString, BEncodingType>> {
+        let found = self.type_name();
+        self.as_dict_mut().ok_or(TypeError { expected: "dictionary", found })
+    }
+
+    pub fn try_into_int(self) -> Result<i64> {
+        match self {
+            BEncodingType::Integer(i) => Ok(i),
+            other => Err(other.mismatch("integer")),
+        }
+    }
+
+    pub fn try_into_bytes(self) -> Result<ByteString> {
+        match self {
+            BEncodingType::String(s) => Ok(s),
+            other => Err(other.mismatch("string")),
+        }
+    }
+
+    pub fn try_into_list(self) -> Result<Vec<BEncodingType>> {
+        match self {
+            BEncodingType::List(list) => Ok(list),
+            other => Err(other.mismatch("list")),
+        }
+    }
+
+    pub fn try_into_dict(self) -> Result<LinkedHashMap<ByteString, BEncodingType>> {
+        match self {
+            BEncodingType::Dictionary(dict) => Ok(dict),
+            other => Err(other.mismatch("dictionary")),
+        }
+    }
+
+    // The dictionary entry or list item at `segment`, if this value has one
+    pub fn get<'p>(&self, segment: impl Into<PathSegment<'p>>) -> Option<&BEncodingType> {
+        match (self, segment.into()) {
+            (BEncodingType::Dictionary(dict), PathSegment::Key(key)) => dict.get(key),
+            (BEncodingType::List(list), PathSegment::Index(i)) => list.get(i),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut<'p>(&mut self, segment: impl Into<PathSegment<'p>>) -> Option<&mut BEncodingType> {
+        match (self, segment.into()) {
+            (BEncodingType::Dictionary(dict), PathSegment::Key(key)) => dict.get_mut(key),
+            (BEncodingType::List(list), PathSegment::Index(i)) => list.get_mut(i),
+            _ => None,
+        }
+    }
+
+    fn mismatch(&self, expected: &'static str) -> TypeError {
+        TypeError { expected, found: self.type_name() }
+    }
+}
+
+impl Index<&str> for BEncodingType {
+    type Output = BEncodingType;
+
+    fn index(&self, key: &str) -> &BEncodingType {
+        self.get(key).unwrap_or_else(|| panic!("no key '{}' in {}", key, self.type_name()))
+    }
+}
+
+impl IndexMut<&str> for BEncodingType {
+    fn index_mut(&mut self, key: &str) -> &mut BEncodingType {
+        let type_name = self.type_name();
+        self.get_mut(key).unwrap_or_else(|| panic!("no key '{}' in {}", key, type_name))
+    }
+}
+
+impl Index<usize> for BEncodingType {
+    type Output = BEncodingType;
+
+    fn index(&self, i: usize) -> &BEncodingType {
+        self.get(i).unwrap_or_else(|| panic!("no item {} in {}", i, self.type_name()))
+    }
+}
+
+impl IndexMut<usize> for BEncodingType {
+    fn index_mut(&mut self, i: usize) -> &mut BEncodingType {
+        let type_name = self.type_name();
+        self.get_mut(i).unwrap_or_else(|| panic!("no item {} in {}", i, type_name))
+    }
+}
+
+impl From<i64> for BEncodingType {
+    fn from(i: i64) -> Self {
+        BEncodingType::Integer(i)
+    }
+}
+
+impl From<&str> for BEncodingType {
+    fn from(s: &str) -> Self {
+        BEncodingType::String(ByteString(s.as_bytes().to_vec()))
+    }
+}
+
+impl From<String> for BEncodingType {
+    fn from(s: String) -> Self {
+        BEncodingType::String(ByteString(s.into_bytes()))
+    }
+}
+
+impl From<&[u8]> for BEncodingType {
+    fn from(bytes: &[u8]) -> Self {
+        BEncodingType::String(ByteString(bytes.to_vec()))
+    }
+}
+
+impl From<Vec<u8>> for BEncodingType {
+    fn from(bytes: Vec<u8>) -> Self {
+        BEncodingType::String(ByteString(bytes))
+    }
+}
+
+impl From<ByteString> for BEncodingType {
+    fn from(s: ByteString) -> Self {
+        BEncodingType::String(s)
+    }
+}
+
+impl From<Vec<BEncodingType>> for BEncodingType {
+    fn from(list: Vec<BEncodingType>) -> Self {
+        BEncodingType::List(list)
+    }
+}
+
+impl From<LinkedHashMap<ByteString, BEncodingType>> for BEncodingType {
+    fn from(dict: LinkedHashMap<ByteString, BEncodingType>) -> Self {
+        BEncodingType::Dictionary(dict)
+    }
+}
+
+impl TryFrom<BEncodingType> for i64 {
+    type Error = TypeError;
+
+    fn try_from(value: BEncodingType) -> Result<Self> {
+        value.try_into_int()
+    }
+}
+
+impl TryFrom<BEncodingType> for ByteString {
+    type Error = TypeError;
+
+    fn try_from(value: BEncodingType) -> Result<Self> {
+        value.try_into_bytes()
+    }
+}
+
+impl TryFrom<BEncodingType> for Vec<u8> {
+    type Error = TypeError;
+
+    fn try_from(value: BEncodingType) -> Result<Self> {
+        value.try_into_bytes().map(|s| s.0)
+    }
+}
+
+impl TryFrom<BEncodingType> for Vec<BEncodingType> {
+    type Error = TypeError;
+
+    fn try_from(value: BEncodingType) -> Result<Self> {
+        value.try_into_list()
+    }
+}
+
+impl TryFrom<BEncodingType> for LinkedHashMap<ByteString, BEncodingType> {
+    type Error = TypeError;
+
+    fn try_from(value: BEncodingType) -> Result<Self> {
+        value.try_into_dict()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bdecode::decode;
+    use crate::bytestring::ToByteString;
+
+    use super::*;
+
+    #[test]
+    fn borrows_matching_types() {
+        let value = decode(b"d1:ai-3e1:b2:\xffx1:lli1ee1:ddee").unwrap();
+        assert_eq!(4, value.as_dict().unwrap().len());
+
+        assert_eq!(Some(-3), value["a"].as_int());
+        assert_eq!(Some(b"\xffx".as_slice()), value["b"].as_bytes());
+        assert_eq!(Some("\u{fffd}x"), value["b"].as_str_lossy().as_deref());
+        assert_eq!(Some([BEncodingType::Integer(1)].as_slice()), value["l"].as_list());
+        assert!(value["d"].as_dict().unwrap().is_empty());
+
+        assert_eq!(None, value.as_int());
+        assert_eq!(None, value.as_bytes());
+        assert_eq!(None, value.as_str_lossy());
+        assert_eq!(None, value.as_list());
+        assert_eq!(None, BEncodingType::Integer(1).as_dict());
+    }
+
+    #[test]
+    fn indexes_dicts_and_lists() {
+        let mut value = decode(b"d4:infod4:name1:a5:filesli1ei2eeee").unwrap();
+
+        assert_eq!(BEncodingType::String("a".to_byte_string()), value["info"]["name"]);
+        assert_eq!(Some(2), value["info"]["files"][1].as_int());
+        value["info"]["files"][0] = BEncodingType::Integer(5);
+        assert_eq!(Some(&BEncodingType::Integer(5)), value["info"]["files"].get(0));
+
+        assert_eq!(None, value.get("missing"));
+        assert_eq!(None, value.get(0));
+        assert_eq!(None, value["info"]["files"].get("name"));
+        assert_eq!(None, value["info"]["files"].get(2));
+        if let Some(name) = value["info"].get_mut(b"name".as_slice()) {
+            *name = BEncodingType::Integer(0);
+        }
+        assert_eq!(Some(0), value["info"]["name"].as_int());
+    }
+
+    #[test]
+    fn edits_in_place() {
+        let mut value = decode(b"d8:announce3:url4:infod5:filesleee").unwrap();
+
+        assert_eq!(Ok(None), value.insert("comment", "hi"));
+        assert_eq!(Ok(Some(BEncodingType::String("url".to_byte_string()))), value.remove("announce"));
+        assert_eq!(Ok(None), value.remove(b"missing"));
+        value["info"]["files"].push(3i64).unwrap();
+        value["info"]["files"].as_list_mut().unwrap().push(BEncodingType::Integer(4));
+        *value.entry("count").unwrap().or_insert(BEncodingType::Integer(0)) = BEncodingType::Integer(1);
+        value.as_dict_mut().unwrap().remove(b"info".as_slice());
+        assert_eq!(decode(b"d7:comment2:hi5:counti1ee").unwrap(), value);
+
+        let mut list = BEncodingType::List(vec![]);
+        assert_eq!(Err(TypeError { expected: "dictionary", found: "list" }), list.insert("a", 1i64));
+        assert_eq!(Err(TypeError { expected: "dictionary", found: "list" }), list.remove("a"));
+        assert!(list.entry("a").is_err());
+        assert_eq!(Err(TypeError { expected: "list", found: "integer" }), BEncodingType::Integer(1).push(1i64));
+        assert_eq!(None, list.as_dict_mut());
+    }
+
+    #[test]
+    fn converts_with_from_and_try_from() {
+        let mut dict = LinkedHashMap::new();
+        dict.insert("a".to_byte_string(), BEncodingType::from(1));
+        dict.insert("b".to_byte_string(), "x".into());
+        dict.insert("c".to_byte_string(), b"\xff".as_slice().into());
+        dict.insert("d".to_byte_string(), vec![BEncodingType::from(String::from("y")), vec![0u8].into()].into());
+        let value = BEncodingType::from(dict.clone());
+        assert_eq!(decode(b"d1:ai1e1:b1:x1:c1:\xff1:dl1:y1:\x00ee").unwrap(), value);
+
+        assert_eq!(Ok(dict), LinkedHashMap::try_from(value.clone()));
+        assert_eq!(Ok(1), i64::try_from(value["a"].clone()));
+        assert_eq!(Ok(b"x".to_vec()), Vec::<u8>::try_from(value["b"].clone()));
+        assert_eq!(Ok("x".to_byte_string()), ByteString::try_from(value["b"].clone()));
+        assert_eq!(Ok(2), Vec::<BEncodingType>::try_from(value["d"].clone()).map(|list| list.len()));
+        let res: Result<i64> = value.try_into();
+        assert_eq!(Err(TypeError { expected: "integer", found: "dictionary" }), res);
+    }
+
+    #[test]
+    #[should_panic(expected = "no key 'missing' in dictionary")]
+    fn missing_key_panics() {
+        let _ = &decode(b"de").unwrap()["missing"];
+    }
+
+    #[test]
+    #[should_panic(expected = "no item 0 in integer")]
+    fn indexing_scalar_panics() {
+        let _ = &BEncodingType::Integer(1)[0];
+    }
+
+    #[test]
+    fn converts_or_names_both_types() {
+        assert_eq!(Ok(7), BEncodingType::Integer(7).try_into_int());
+        assert_eq!(Ok("x".to_byte_string()), BEncodingType::String("x".to_byte_string()).try_into_bytes());
+        assert_eq!(Ok(vec![]), BEncodingType::List(vec![]).try_into_list());
+        assert_eq!(Ok(LinkedHashMap::new()), BEncodingType::Dictionary(LinkedHashMap::new()).try_into_dict());
+
+        let err = BEncodingType::List(vec![]).try_into_int().unwrap_err();
+        assert_eq!(TypeError { expected: "integer", found: "list" }, err);
+        assert_eq!("Expected integer but found list", err.to_string());
+        assert_eq!(Err(TypeError { expected: "dictionary", found: "string" }),
+                   BEncodingType::String("x".to_byte_string()).try_into_dict());
+    }
+}
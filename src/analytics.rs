@@ -0,0 +1,89 @@
+use crate::bytestring::{ByteString, ToByteString};
+use crate::error::DecodingError;
+use crate::index::{Kind, PathSegment, StructureIndex};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Scalar {
+    Integer(i64),
+    Bytes(ByteString),
+}
+
+// Values pulled out of a corpus in columnar form. There is one column per
+// requested path and one row per document that could be indexed. A row holds
+// None where the path is missing or points at a container.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RecordBatch {
+    pub columns: Vec<Vec<Option<Scalar>>>,
+    // Corpus position and error of each document that failed to decode
+    pub errors: Vec<(usize, DecodingError)>,
+}
+
+impl RecordBatch {
+    pub fn num_rows(&self) -> usize {
+        self.columns.first().map_or(0, Vec::len)
+    }
+}
+
+// Extracts the scalars at `paths` from every document in `corpus`. Documents
+// are only indexed, never fully decoded, so large unused fields such as
+// `pieces` cost a skip rather than a copy.
+pub fn extract<I, D>(corpus: I, paths: &[&[PathSegment]]) -> RecordBatch
+    where I: IntoIterator<Item = D>, D: AsRef<[u8]> {
+    let mut batch = RecordBatch { columns: vec![Vec::new(); paths.len()], errors: Vec::new() };
+    for (pos, doc) in corpus.into_iter().enumerate() {
+        let index = match StructureIndex::build(doc.as_ref()) {
+            Ok(index) => index,
+            Err(err) => {
+                batch.errors.push((pos, err));
+                continue;
+            }
+        };
+        for (column, path) in batch.columns.iter_mut().zip(paths) {
+            column.push(index.find(path).and_then(|node| scalar_at(&index, node)));
+        }
+    }
+    batch
+}
+
+fn scalar_at(index: &StructureIndex, node: usize) -> Option<Scalar> {
+    match index.nodes()[node].kind {
+        Kind::Integer => {
+            let raw = index.raw(node)?;
+            std::str::from_utf8(&raw[1..raw.len() - 1]).ok()?.parse().ok().map(Scalar::Integer)
+        }
+        Kind::String => index.str_content(node).map(|bytes| Scalar::Bytes(bytes.to_byte_string())),
+        Kind::List | Kind::Dictionary => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extracts_columns() {
+        let corpus: Vec<&[u8]> = vec![
+            b"d8:announce3:one4:infod6:lengthi10e4:name1:aee",
+            b"d4:infod6:lengthi-20e4:name1:bee",
+            b"d8:announce",
+            b"d8:announce3:two4:infod6:lengthli1ee4:name1:cee",
+        ];
+        let batch = extract(corpus, &[&["announce".into()], &["info".into(), "length".into()]]);
+
+        assert_eq!(3, batch.num_rows());
+        assert_eq!(vec![
+            Some(Scalar::Bytes("one".to_byte_string())),
+            None,
+            Some(Scalar::Bytes("two".to_byte_string())),
+        ], batch.columns[0]);
+        assert_eq!(vec![Some(Scalar::Integer(10)), Some(Scalar::Integer(-20)), None], batch.columns[1]);
+        assert_eq!(vec![(2, DecodingError::KeyWithoutValue("announce".to_byte_string()))], batch.errors);
+    }
+
+    #[test]
+    fn empty_corpus() {
+        let batch = extract(Vec::<Vec<u8>>::new(), &[&["announce".into()]]);
+        assert_eq!(0, batch.num_rows());
+        assert_eq!(vec![Vec::<Option<Scalar>>::new()], batch.columns);
+    }
+}
@@ -0,0 +1,85 @@
+// Decoding from tokio's AsyncRead, for response bodies that should be parsed
+// while they stream in. Chunks go through a push::Decoder as they arrive.
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::bdecode::{decode, BEncodingType};
+use crate::error::DecodingError;
+use crate::push::{DecodeProgress, Decoder, DEFAULT_MAX_BUFFERED};
+
+const CHUNK: usize = 8 * 1024;
+
+// Decodes a single value, awaiting more input until it is complete. Errors
+// match those of decode for the same bytes, plus DecodingError::Io when
+// reading fails. Bytes after the value may have been read from `reader`.
+//
+// Values larger than DEFAULT_MAX_BUFFERED fail with SizeLimitExceeded; see
+// decode_async_with_limit.
+pub async fn decode_async<R: AsyncRead + Unpin>(reader: R) -> Result<BEncodingType, DecodingError> {
+    decode_async_with_limit(reader, DEFAULT_MAX_BUFFERED).await
+}
+
+// Like decode_async, giving up once more than `max_bytes` have been read
+// without completing the value
+pub async fn decode_async_with_limit<R: AsyncRead + Unpin>(mut reader: R, max_bytes: usize)
+    -> Result<BEncodingType, DecodingError> {
+    let mut decoder = Decoder::new().max_buffered(max_bytes);
+    let mut chunk = vec![0; CHUNK];
+    loop {
+        let n = reader.read(&mut chunk).await.map_err(|err| DecodingError::Io(err.kind()))?;
+        if n == 0 {
+            // The input ended early, let the decoder say where
+            return decode(decoder.buffered());
+        }
+        if let DecodeProgress::Done(value) = decoder.feed(&chunk[..n])? {
+            return Ok(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::Future;
+
+    use super::*;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn decodes_streamed_input() {
+        let mut body = b"d8:intervali1800e5:peers6000:".to_vec();
+        body.extend(vec![7; 6000]);
+        body.extend(b"ei1e");
+        let (mut tx, rx) = tokio::io::duplex(64);
+        let expected = decode(&body);
+
+        let value = block_on(async move {
+            let writer = tokio::spawn(async move {
+                use tokio::io::AsyncWriteExt;
+                for part in body.chunks(100) {
+                    tx.write_all(part).await.unwrap();
+                }
+            });
+            let value = decode_async(rx).await;
+            writer.await.unwrap();
+            value
+        });
+        assert_eq!(expected, value);
+    }
+
+    #[test]
+    fn reports_truncated_input() {
+        assert_eq!(Err(DecodingError::UnterminatedList { start_offset: 0 }), block_on(decode_async(&b"li1e"[..])));
+        assert_eq!(Err(DecodingError::EndOfFile), block_on(decode_async(&b""[..])));
+        assert_eq!(Err(DecodingError::NotANumber), block_on(decode_async(&b"ixe"[..])));
+    }
+
+    #[test]
+    fn stops_at_size_limit() {
+        let body = b"999999999:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+        assert_eq!(Err(DecodingError::SizeLimitExceeded(32)), block_on(decode_async_with_limit(&body[..], 32)));
+        assert_eq!(Ok(BEncodingType::Integer(5)), block_on(decode_async_with_limit(&b"i5e"[..], 3)));
+    }
+}
@@ -1,50 +1,146 @@
+use std::io::{self, Read};
+use std::time::Instant;
+
 use linked_hash_map::LinkedHashMap;
 
+use crate::bounded::CancelToken;
 use crate::bytestring::{ByteString, ToByteString};
 use crate::error::DecodingError;
+use crate::index::PathSegment;
 
 type Result<T> = std::result::Result<T, DecodingError>;
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+// Deepest container nesting the decoder accepts. Parsing is recursive, so this
+// bounds stack usage for adversarial input such as "llllllll...".
+pub const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum BEncodingType {
     Integer(i64),
-    // TODO: Use the original slice inside the input instead of copying it
     String(ByteString),
     List(Vec<BEncodingType>),
     Dictionary(LinkedHashMap<ByteString, BEncodingType>),
 }
 
+// Same shape as BEncodingType, but strings and keys point into the input
+// instead of being copied. Cheaper for documents with large strings such as
+// the `pieces` field of a torrent.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum BEncodingRef<'a> {
+    Integer(i64),
+    String(&'a [u8]),
+    List(Vec<BEncodingRef<'a>>),
+    Dictionary(LinkedHashMap<&'a [u8], BEncodingRef<'a>>),
+}
+
+impl BEncodingRef<'_> {
+    // Copies the value out of the input buffer
+    pub fn to_value(&self) -> BEncodingType {
+        match self {
+            BEncodingRef::Integer(i) => BEncodingType::Integer(*i),
+            BEncodingRef::String(s) => BEncodingType::String(s.to_byte_string()),
+            BEncodingRef::List(list) => BEncodingType::List(list.iter().map(BEncodingRef::to_value).collect()),
+            BEncodingRef::Dictionary(dict) => BEncodingType::Dictionary(
+                dict.iter().map(|(key, value)| (key.to_byte_string(), value.to_value())).collect()
+            ),
+        }
+    }
+}
+
+// Location of a byte string in the input: where its length prefix starts,
+// where its content starts, and where it ends.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) struct StrSpan {
+    pub(crate) start: usize,
+    pub(crate) content: usize,
+    pub(crate) end: usize,
+}
+
 pub struct BDecoder<'a> {
     bytes: &'a [u8],
     cursor: usize,
+    depth: usize,
+    deadline: Option<Instant>,
+    cancel: Option<CancelToken>,
 }
 
-impl BDecoder<'_> {
-    fn new(bytes: &[u8]) -> BDecoder {
-        BDecoder { bytes, cursor: 0 }
+impl<'a> BDecoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> BDecoder<'a> {
+        BDecoder { bytes, cursor: 0, depth: 0, deadline: None, cancel: None }
+    }
+
+    // Makes the decoder give up once `deadline` has passed or `cancel` is set.
+    // Both are checked before every value.
+    pub(crate) fn with_budget(mut self, deadline: Option<Instant>, cancel: Option<CancelToken>) -> Self {
+        self.deadline = deadline;
+        self.cancel = cancel;
+        self
+    }
+
+    // Points the decoder at a new input so a single instance can be reused
+    // across many small messages. The budget from with_budget belonged to the
+    // previous message and is dropped too. There is no other scratch to keep:
+    // parsing recurses on the call stack and keys are copied straight into the
+    // decoded value, which the caller owns.
+    pub fn reset(&mut self, bytes: &'a [u8]) {
+        self.bytes = bytes;
+        self.cursor = 0;
+        self.depth = 0;
+        self.deadline = None;
+        self.cancel = None;
     }
 
-    fn decode(&mut self) -> Result<BEncodingType> {
+    pub fn decode(&mut self) -> Result<BEncodingType> {
         self.parse_type()
     }
 
+    pub fn decode_borrowed(&mut self) -> Result<BEncodingRef<'a>> {
+        self.parse_ref()
+    }
+
+    pub(crate) fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    // Continues decoding from `cursor`, for callers that keep their own state
+    // about what comes before it
+    pub(crate) fn seek_to(&mut self, cursor: usize) {
+        self.cursor = cursor;
+    }
+
     fn parse_str(&mut self) -> Result<ByteString> {
-        let len = self.read_num().or(Err(DecodingError::StringWithoutLength))?;
+        let span = self.read_str_span()?;
+        Ok(self.str_at(span))
+    }
+
+    pub(crate) fn str_at(&self, span: StrSpan) -> ByteString {
+        (&self.bytes[span.content..span.end]).to_byte_string()
+    }
+
+    pub(crate) fn read_str_span(&mut self) -> Result<StrSpan> {
+        let start = self.cursor;
+        let len = self.read_num().map_err(|err| match err {
+            DecodingError::IntegerOverflow => err,
+            _ => DecodingError::StringWithoutLength,
+        })?;
         if len < 0 {
             return Err(DecodingError::NegativeStringLen);
         }
         self.expect_char(b':')?;
-        let start = self.cursor;
-        let end = start + len as usize;
-        if end > self.bytes.len() {
-            self.cursor = self.bytes.len();
-            return Err(DecodingError::EndOfFile);
-        }
+        let content = self.cursor;
+        // A length that doesn't fit in usize can't fit in the input either
+        let end = match usize::try_from(len).ok().and_then(|len| content.checked_add(len)) {
+            Some(end) if end <= self.bytes.len() => end,
+            _ => {
+                self.cursor = self.bytes.len();
+                return Err(DecodingError::EndOfFile);
+            }
+        };
         self.cursor = end;
-        Ok((&self.bytes[start..end]).to_byte_string())
+        Ok(StrSpan { start, content, end })
     }
 
-    fn parse_int(&mut self) -> Result<i64> {
+    pub(crate) fn parse_int(&mut self) -> Result<i64> {
         self.expect_char(b'i')?;
         let i = self.read_num()?;
         self.expect_char(b'e')?;
@@ -52,62 +148,194 @@ impl BDecoder<'_> {
     }
 
     fn parse_list(&mut self) -> Result<Vec<BEncodingType>> {
-        self.expect_char(b'l')?;
         let mut list = Vec::new();
-        while self.peek()? != b'e' {
-            list.push(self.parse_type()?);
-        }
-        self.expect_char(b'e')?;
+        self.walk_list(|decoder| {
+            list.push(decoder.parse_type()?);
+            Ok(())
+        })?;
         Ok(list)
     }
 
     fn parse_dict(&mut self) -> Result<LinkedHashMap<ByteString, BEncodingType>> {
-        self.expect_char(b'd')?;
         let mut dict = LinkedHashMap::new();
-        while self.peek()? != b'e' {
-            let key = self.parse_str()?;
-            let value = self.parse_type()
-                .map_err(|_| DecodingError::KeyWithoutValue(key.clone()))?;
-            dict.insert(key, value);
+        self.walk_dict(|decoder, key| {
+            let value = decoder.parse_type()?;
+            dict.insert(decoder.str_at(key), value);
+            Ok(())
+        })?;
+        Ok(dict)
+    }
+
+    // Consumes a list, leaving each item to `item`
+    pub(crate) fn walk_list<F>(&mut self, mut item: F) -> Result<()>
+        where F: FnMut(&mut Self) -> Result<()> {
+        let start_offset = self.cursor;
+        self.expect_char(b'l')?;
+        while self.peek().or(Err(DecodingError::UnterminatedList { start_offset }))? != b'e' {
+            item(self)?;
         }
         self.expect_char(b'e')?;
-        Ok(dict)
+        Ok(())
     }
 
-    fn parse_type(&mut self) -> Result<BEncodingType> {
+    // Consumes a dictionary, reading each key and leaving its value to `value`.
+    // Errors from `value` are attributed to the key, see in_value_of.
+    pub(crate) fn walk_dict<F>(&mut self, mut value: F) -> Result<()>
+        where F: FnMut(&mut Self, StrSpan) -> Result<()> {
+        let start_offset = self.cursor;
+        self.expect_char(b'd')?;
+        while self.peek().or(Err(DecodingError::UnterminatedDictionary { start_offset }))? != b'e' {
+            let key = self.read_str_span()?;
+            if matches!(self.peek(), Ok(b'e') | Err(_)) {
+                return Err(DecodingError::KeyWithoutValue(self.str_at(key)));
+            }
+            if let Err(err) = value(self, key) {
+                return Err(err.in_value_of(self.str_at(key)));
+            }
+        }
+        self.expect_char(b'e')?;
+        Ok(())
+    }
+
+    fn parse_ref(&mut self) -> Result<BEncodingRef<'a>> {
+        self.check_budget()?;
+        match self.peek()? {
+            b'i' => self.parse_int().map(BEncodingRef::Integer),
+            b'l' => self.nested(|decoder| {
+                let mut list = Vec::new();
+                decoder.walk_list(|decoder| {
+                    list.push(decoder.parse_ref()?);
+                    Ok(())
+                })?;
+                Ok(BEncodingRef::List(list))
+            }),
+            b'd' => self.nested(|decoder| {
+                let mut dict = LinkedHashMap::new();
+                decoder.walk_dict(|decoder, key| {
+                    let value = decoder.parse_ref()?;
+                    dict.insert(decoder.slice_at(key), value);
+                    Ok(())
+                })?;
+                Ok(BEncodingRef::Dictionary(dict))
+            }),
+            _ => {
+                let span = self.read_str_span()?;
+                Ok(BEncodingRef::String(self.slice_at(span)))
+            }
+        }
+    }
+
+    fn slice_at(&self, span: StrSpan) -> &'a [u8] {
+        &self.bytes[span.content..span.end]
+    }
+
+    // Moves past the value at the cursor without building anything
+    pub(crate) fn skip_value(&mut self) -> Result<()> {
+        self.check_budget()?;
+        match self.peek()? {
+            b'i' => self.parse_int().map(|_| ()),
+            b'l' => self.nested(|decoder| decoder.walk_list(Self::skip_value)),
+            b'd' => self.nested(|decoder| decoder.walk_dict(|decoder, _| decoder.skip_value())),
+            _ => self.read_str_span().map(|_| ()),
+        }
+    }
+
+    // Moves the cursor to the value at `path`, skipping everything before it.
+    // Returns false if the path does not exist.
+    fn seek(&mut self, path: &[PathSegment]) -> Result<bool> {
+        for segment in path {
+            let start_offset = self.cursor;
+            match (segment, self.peek()?) {
+                (PathSegment::Key(wanted), b'd') => {
+                    self.expect_char(b'd')?;
+                    loop {
+                        if self.peek().or(Err(DecodingError::UnterminatedDictionary { start_offset }))? == b'e' {
+                            return Ok(false);
+                        }
+                        let key = self.read_str_span()?;
+                        if self.slice_at(key) == *wanted {
+                            break;
+                        }
+                        self.skip_value()?;
+                    }
+                }
+                (PathSegment::Index(wanted), b'l') => {
+                    self.expect_char(b'l')?;
+                    for _ in 0..*wanted {
+                        if self.peek().or(Err(DecodingError::UnterminatedList { start_offset }))? == b'e' {
+                            return Ok(false);
+                        }
+                        self.skip_value()?;
+                    }
+                    if self.peek().or(Err(DecodingError::UnterminatedList { start_offset }))? == b'e' {
+                        return Ok(false);
+                    }
+                }
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    pub(crate) fn parse_type(&mut self) -> Result<BEncodingType> {
+        self.check_budget()?;
         match self.peek()? {
             b'i' => self.parse_int().map(BEncodingType::Integer),
-            b'l' => self.parse_list().map(BEncodingType::List),
-            b'd' => self.parse_dict().map(BEncodingType::Dictionary),
+            b'l' => self.nested(Self::parse_list).map(BEncodingType::List),
+            b'd' => self.nested(Self::parse_dict).map(BEncodingType::Dictionary),
             _ => self.parse_str().map(BEncodingType::String)
         }
     }
 
+    pub(crate) fn nested<T, F>(&mut self, parse: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T> {
+        if self.depth == MAX_DEPTH {
+            return Err(DecodingError::NestingTooDeep(MAX_DEPTH));
+        }
+        self.depth += 1;
+        let res = parse(self);
+        self.depth -= 1;
+        res
+    }
+
+    pub(crate) fn check_budget(&self) -> Result<()> {
+        if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return Err(DecodingError::Cancelled);
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(DecodingError::TimedOut);
+        }
+        Ok(())
+    }
+
     fn read_num(&mut self) -> Result<i64> {
-        let mut neg_const = 1;
-        if self.peek()? == b'-' {
-            neg_const = -1;
+        let negative = self.peek()? == b'-';
+        if negative {
             self.cursor += 1;
         }
         let chr = self.peek()?;
         if !chr.is_ascii_digit() {
             return Err(DecodingError::NotANumber);
-        } else if neg_const == -1 && chr == b'0' {
+        } else if negative && chr == b'0' {
             return Err(DecodingError::NegativeZero);
         }
-        let mut acc = 0;
+        // Negative numbers are accumulated downwards so i64::MIN is reachable
+        let mut acc: i64 = 0;
         while let Ok(v) = self.peek() {
             if v.is_ascii_digit() {
-                acc = acc * 10 + (v - b'0') as i64;
+                let digit = (v - b'0') as i64;
+                acc = acc.checked_mul(10)
+                    .and_then(|acc| if negative { acc.checked_sub(digit) } else { acc.checked_add(digit) })
+                    .ok_or(DecodingError::IntegerOverflow)?;
                 self.cursor += 1;
             } else {
                 break;
             }
         };
-        Ok(acc * neg_const)
+        Ok(acc)
     }
 
-    fn expect_char(&mut self, expected: u8) -> Result<u8> {
+    pub(crate) fn expect_char(&mut self, expected: u8) -> Result<u8> {
         if expected == self.peek()? {
             self.advance()
         } else {
@@ -115,7 +343,7 @@ impl BDecoder<'_> {
         }
     }
 
-    fn peek(&mut self) -> Result<u8> {
+    pub(crate) fn peek(&mut self) -> Result<u8> {
         self.bytes.get(self.cursor).cloned()
             .ok_or(DecodingError::EndOfFile)
     }
@@ -127,15 +355,221 @@ impl BDecoder<'_> {
     }
 }
 
+// Decodes a single value from the start of `inp`.
+//
+// The decoder never panics: every malformed, truncated or hostile input is
+// reported as a DecodingError. Integers that overflow i64, string lengths past
+// the end of the input and nesting deeper than MAX_DEPTH are all errors.
 pub fn decode(inp: &[u8]) -> Result<BEncodingType> {
     let mut parser = BDecoder::new(inp);
     parser.decode()
 }
 
+// Like decode, but strings borrow from `inp` instead of being copied
+pub fn decode_borrowed(inp: &[u8]) -> Result<BEncodingRef<'_>> {
+    BDecoder::new(inp).decode_borrowed()
+}
+
+// Size of the buffer decode_from_reader reads into, and the largest chunk a
+// string grows by, so a bogus length can't trigger a huge allocation upfront.
+const READ_CHUNK: usize = 8 * 1024;
+
+// Recursive decoder over an io::Read with the same grammar and errors as
+// BDecoder. Input is pulled into a small buffer as the parser needs it.
+struct ReadDecoder<R> {
+    reader: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    filled: usize,
+    // Input offset of buf[0]
+    offset: usize,
+    depth: usize,
+}
+
+impl<R: Read> ReadDecoder<R> {
+    fn new(reader: R) -> ReadDecoder<R> {
+        ReadDecoder { reader, buf: vec![0; READ_CHUNK].into_boxed_slice(), pos: 0, filled: 0, offset: 0, depth: 0 }
+    }
+
+    fn position(&self) -> usize {
+        self.offset + self.pos
+    }
+
+    fn peek(&mut self) -> Result<u8> {
+        if self.pos == self.filled {
+            self.offset += self.filled;
+            self.pos = 0;
+            self.filled = read_retrying(&mut self.reader, &mut self.buf)?;
+        }
+        self.buf[..self.filled].get(self.pos).cloned().ok_or(DecodingError::EndOfFile)
+    }
+
+    fn expect_char(&mut self, expected: u8) -> Result<()> {
+        if expected == self.peek()? {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(DecodingError::MissingIdentifier(expected as char))
+        }
+    }
+
+    fn read_num(&mut self) -> Result<i64> {
+        let negative = self.peek()? == b'-';
+        if negative {
+            self.pos += 1;
+        }
+        let chr = self.peek()?;
+        if !chr.is_ascii_digit() {
+            return Err(DecodingError::NotANumber);
+        } else if negative && chr == b'0' {
+            return Err(DecodingError::NegativeZero);
+        }
+        let mut acc: i64 = 0;
+        loop {
+            match self.peek() {
+                Ok(v) if v.is_ascii_digit() => {
+                    let digit = (v - b'0') as i64;
+                    acc = acc.checked_mul(10)
+                        .and_then(|acc| if negative { acc.checked_sub(digit) } else { acc.checked_add(digit) })
+                        .ok_or(DecodingError::IntegerOverflow)?;
+                    self.pos += 1;
+                }
+                Ok(_) | Err(DecodingError::EndOfFile) => return Ok(acc),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn parse_str(&mut self) -> Result<ByteString> {
+        let len = self.read_num().map_err(|err| match err {
+            DecodingError::IntegerOverflow | DecodingError::Io(_) => err,
+            _ => DecodingError::StringWithoutLength,
+        })?;
+        if len < 0 {
+            return Err(DecodingError::NegativeStringLen);
+        }
+        self.expect_char(b':')?;
+
+        let mut remaining = usize::try_from(len).or(Err(DecodingError::EndOfFile))?;
+        let mut out = Vec::with_capacity(remaining.min(READ_CHUNK));
+        while remaining > 0 {
+            self.peek()?;
+            let take = remaining.min(self.filled - self.pos);
+            out.extend_from_slice(&self.buf[self.pos..self.pos + take]);
+            self.pos += take;
+            remaining -= take;
+        }
+        Ok(ByteString(out))
+    }
+
+    fn parse_type(&mut self) -> Result<BEncodingType> {
+        match self.peek()? {
+            b'i' => {
+                self.pos += 1;
+                let i = self.read_num()?;
+                self.expect_char(b'e')?;
+                Ok(BEncodingType::Integer(i))
+            }
+            b'l' => self.nested(Self::parse_list).map(BEncodingType::List),
+            b'd' => self.nested(Self::parse_dict).map(BEncodingType::Dictionary),
+            _ => self.parse_str().map(BEncodingType::String),
+        }
+    }
+
+    fn nested<T>(&mut self, parse: fn(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth == MAX_DEPTH {
+            return Err(DecodingError::NestingTooDeep(MAX_DEPTH));
+        }
+        self.depth += 1;
+        let res = parse(self);
+        self.depth -= 1;
+        res
+    }
+
+    fn parse_list(&mut self) -> Result<Vec<BEncodingType>> {
+        let start_offset = self.position();
+        self.expect_char(b'l')?;
+        let mut list = Vec::new();
+        while self.peek_in_container(DecodingError::UnterminatedList { start_offset })? != b'e' {
+            list.push(self.parse_type()?);
+        }
+        self.pos += 1;
+        Ok(list)
+    }
+
+    fn parse_dict(&mut self) -> Result<LinkedHashMap<ByteString, BEncodingType>> {
+        let start_offset = self.position();
+        self.expect_char(b'd')?;
+        let mut dict = LinkedHashMap::new();
+        while self.peek_in_container(DecodingError::UnterminatedDictionary { start_offset })? != b'e' {
+            let key = self.parse_str()?;
+            match self.peek() {
+                Ok(b'e') | Err(DecodingError::EndOfFile) => return Err(DecodingError::KeyWithoutValue(key)),
+                Err(err) => return Err(err),
+                Ok(_) => {}
+            }
+            match self.parse_type() {
+                Ok(value) => { dict.insert(key, value); }
+                Err(err) => return Err(err.in_value_of(key)),
+            }
+        }
+        self.pos += 1;
+        Ok(dict)
+    }
+
+    // Peeks inside a container, reporting running out of input as `unterminated`
+    fn peek_in_container(&mut self, unterminated: DecodingError) -> Result<u8> {
+        match self.peek() {
+            Err(DecodingError::EndOfFile) => Err(unterminated),
+            res => res,
+        }
+    }
+}
+
+fn read_retrying<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    loop {
+        match reader.read(buf) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            res => return res.map_err(|err| DecodingError::Io(err.kind())),
+        }
+    }
+}
+
+// Decodes a single value from `reader` without loading the whole input first.
+// Errors match those of decode for the same bytes, plus DecodingError::Io when
+// reading fails. The reader is consumed in chunks, so bytes after the value
+// may have been read from it as well.
+pub fn decode_from_reader<R: Read>(reader: R) -> Result<BEncodingType> {
+    ReadDecoder::new(reader).parse_type()
+}
+
+// Reads the integer at `path` straight from the encoded input, skipping over
+// everything before it. Returns None if the path does not exist or does not
+// lead to an integer. Input after the value is not looked at.
+pub fn read_int_at(inp: &[u8], path: &[PathSegment]) -> Result<Option<i64>> {
+    let mut decoder = BDecoder::new(inp);
+    if !decoder.seek(path)? || decoder.peek()? != b'i' {
+        return Ok(None);
+    }
+    decoder.parse_int().map(Some)
+}
+
+// Like read_int_at, for byte strings. The result borrows from `inp`.
+pub fn read_bytes_at<'a>(inp: &'a [u8], path: &[PathSegment]) -> Result<Option<&'a [u8]>> {
+    let mut decoder = BDecoder::new(inp);
+    if !decoder.seek(path)? || !decoder.peek()?.is_ascii_digit() {
+        return Ok(None);
+    }
+    let span = decoder.read_str_span()?;
+    Ok(Some(decoder.slice_at(span)))
+}
+
 // TODO: Add tests for some real world examples
 // TODO: Add benchmarks
 #[cfg(test)]
 mod test {
+    use crate::test_util::fuzz_inputs;
+
     use super::*;
 
     #[test]
@@ -163,6 +597,11 @@ mod test {
         assert_eq!((Err(DecodingError::NotANumber), 2), parse_int("i-abc"));
         assert_eq!((Err(DecodingError::MissingIdentifier('e')), 3), parse_int("i23abc"));
         assert_eq!((Err(DecodingError::EndOfFile), 3), parse_int("i23"));
+        assert_eq!((Ok(i64::MAX), 21), parse_int("i9223372036854775807e"));
+        assert_eq!((Ok(i64::MIN), 22), parse_int("i-9223372036854775808e"));
+        assert_eq!((Err(DecodingError::IntegerOverflow), 19), parse_int("i9223372036854775808e"));
+        assert_eq!((Err(DecodingError::IntegerOverflow), 20), parse_int("i-9223372036854775809e"));
+        assert_eq!((Err(DecodingError::IntegerOverflow), 19), parse_int("i99999999999999999999999e"));
     }
 
     #[test]
@@ -178,6 +617,8 @@ mod test {
         assert_eq!((Err(DecodingError::NegativeStringLen), 2), parse_string("-3:abc"));
         assert_eq!((Err(DecodingError::MissingIdentifier(':')), 1), parse_string("3abc"));
         assert_eq!((Err(DecodingError::EndOfFile), 4), parse_string("3:ab"));
+        assert_eq!((Err(DecodingError::EndOfFile), 22), parse_string("9223372036854775807:ab"));
+        assert_eq!((Err(DecodingError::IntegerOverflow), 18), parse_string("99999999999999999999:ab"));
     }
 
     #[test]
@@ -200,7 +641,9 @@ mod test {
             BEncodingType::List(vec![BEncodingType::List(vec![])]),
         ]), 10), parse_list("llleelleee"));
         assert_eq!((Err(DecodingError::MissingIdentifier('l')), 0), parse_list("abc"));
-        assert_eq!((Err(DecodingError::EndOfFile), 6), parse_list("l3:abc"));
+        assert_eq!((Err(DecodingError::UnterminatedList { start_offset: 0 }), 6), parse_list("l3:abc"));
+        assert_eq!((Err(DecodingError::UnterminatedList { start_offset: 1 }), 4), parse_list("llle"));
+        assert_eq!((Err(DecodingError::EndOfFile), 4), parse_list("li12"));
     }
 
     #[test]
@@ -231,6 +674,146 @@ mod test {
 
         assert_eq!((Err(DecodingError::MissingIdentifier('d')), 0), parse_dictionary("abc"));
         assert_eq!((Err(DecodingError::KeyWithoutValue("item".to_byte_string())), 7), parse_dictionary("d4:iteme"));
-        assert_eq!((Err(DecodingError::EndOfFile), 8), parse_dictionary("d1:a2:bc"));
+        assert_eq!((Err(DecodingError::UnterminatedDictionary { start_offset: 0 }), 8), parse_dictionary("d1:a2:bc"));
+        assert_eq!((Err(DecodingError::UnterminatedDictionary { start_offset: 0 }), 1), parse_dictionary("d"));
+        assert_eq!((Err(DecodingError::KeyWithoutValue("item".to_byte_string())), 7), parse_dictionary("d4:item"));
+        assert_eq!((Err(DecodingError::InvalidValueForKey {
+            key: "a".to_byte_string(),
+            source: Box::new(DecodingError::InvalidValueForKey {
+                key: "b".to_byte_string(),
+                source: Box::new(DecodingError::IntegerOverflow),
+            }),
+        }), 27), parse_dictionary("d1:ad1:bi99999999999999999999eee"));
+        assert_eq!((Err(DecodingError::InvalidValueForKey {
+            key: "a".to_byte_string(),
+            source: Box::new(DecodingError::UnterminatedDictionary { start_offset: 4 }),
+        }), 11), parse_dictionary("d1:ad1:bi1e"));
+    }
+
+    #[test]
+    pub fn errors_for_keys_expose_their_source() {
+        use std::error::Error;
+
+        let err = decode(b"d1:ad1:bi-0eee").unwrap_err();
+        let inner = err.source().unwrap();
+        assert_eq!("Invalid value for dictionary key 'b': Negative zero is not allowed. Use 0 instead", inner.to_string());
+        assert_eq!("Negative zero is not allowed. Use 0 instead", inner.source().unwrap().to_string());
+        assert!(inner.source().unwrap().source().is_none());
+    }
+
+    #[test]
+    pub fn nesting_limit() {
+        let nested = |depth: usize| {
+            let mut inp = vec![b'l'; depth];
+            inp.extend(vec![b'e'; depth]);
+            inp
+        };
+
+        assert!(decode(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(Err(DecodingError::NestingTooDeep(MAX_DEPTH)), decode(&nested(MAX_DEPTH + 1)));
+        assert_eq!(Err(DecodingError::NestingTooDeep(MAX_DEPTH)), decode(&vec![b'l'; 1_000_000]));
+
+        // Limits are not attributed to the key they were hit under
+        let mut in_dict = b"d1:ad1:b".to_vec();
+        in_dict.extend(nested(MAX_DEPTH));
+        assert_eq!(Err(DecodingError::NestingTooDeep(MAX_DEPTH)), decode(&in_dict));
+
+        let mut decoder = BDecoder::new(b"llee");
+        assert!(decoder.decode().is_ok());
+        assert_eq!(decoder.depth, 0);
+    }
+
+    // Throws generated and mutated inputs at the decoder. Any panic or stack
+    // overflow fails the test.
+    #[test]
+    pub fn never_panics() {
+        for inp in fuzz_inputs(20_000, 40) {
+            assert_eq!(decode(&inp), decode_borrowed(&inp).map(|value| value.to_value()));
+            assert_eq!(decode(&inp), decode_from_reader(inp.as_slice()));
+        }
+    }
+
+    #[test]
+    pub fn borrowed_strings_point_into_input() {
+        let inp = b"d6:piecesl3:abce3:numi-7ee";
+        let value = decode_borrowed(inp).unwrap();
+        let BEncodingRef::Dictionary(dict) = &value else { panic!("expected a dictionary") };
+        let (key, pieces) = dict.iter().next().unwrap();
+        assert_eq!(inp[3..].as_ptr(), key.as_ptr());
+        assert_eq!(&BEncodingRef::List(vec![BEncodingRef::String(&inp[12..15])]), pieces);
+        assert_eq!(Some(&BEncodingRef::Integer(-7)), dict.get(&b"num"[..]));
+        assert_eq!(decode(inp).unwrap(), value.to_value());
+
+        assert_eq!(Err(DecodingError::KeyWithoutValue("a".to_byte_string())), decode_borrowed(b"d1:ae"));
+        let mut nested = vec![b'l'; MAX_DEPTH + 1];
+        nested.extend(vec![b'e'; MAX_DEPTH + 1]);
+        assert_eq!(Err(DecodingError::NestingTooDeep(MAX_DEPTH)), decode_borrowed(&nested));
+    }
+
+    #[test]
+    pub fn reads_scalars_at_paths() {
+        let torrent = b"d8:announce3:url4:infod6:lengthi1024e4:name1:a5:filesld6:lengthi7eeee6:pieces2:xye";
+        assert_eq!(Ok(Some(1024)), read_int_at(torrent, &["info".into(), "length".into()]));
+        assert_eq!(Ok(Some(7)), read_int_at(torrent, &["info".into(), "files".into(), 0.into(), "length".into()]));
+        assert_eq!(Ok(Some(&b"url"[..])), read_bytes_at(torrent, &["announce".into()]));
+        assert_eq!(Ok(Some(&b"xy"[..])), read_bytes_at(torrent, &["pieces".into()]));
+
+        assert_eq!(Ok(None), read_int_at(torrent, &["info".into(), "name".into()]));
+        assert_eq!(Ok(None), read_bytes_at(torrent, &["info".into(), "length".into()]));
+        assert_eq!(Ok(None), read_int_at(torrent, &["info".into(), "files".into(), 1.into()]));
+        assert_eq!(Ok(None), read_int_at(torrent, &["missing".into()]));
+        assert_eq!(Ok(None), read_int_at(torrent, &[0.into()]));
+
+        // Only the input up to the value is looked at
+        assert_eq!(Ok(Some(1)), read_int_at(b"d1:ai1e1:b", &["a".into()]));
+        assert_eq!(Err(DecodingError::NotANumber), read_int_at(b"d1:aixe1:bi1ee", &["b".into()]));
+        assert_eq!(Err(DecodingError::UnterminatedDictionary { start_offset: 0 }), read_int_at(b"d1:ai1e", &["b".into()]));
+    }
+
+    #[test]
+    pub fn decodes_from_reader() {
+        // Hands out a few bytes per read call to exercise buffer refills, then
+        // fails with the given error if any
+        struct Trickle<'a>(&'a [u8], Option<io::ErrorKind>);
+
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if let (true, Some(kind)) = (self.0.is_empty(), self.1) {
+                    return Err(kind.into());
+                }
+                let n = buf.len().min(self.0.len()).min(3);
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+
+        let mut long = b"d4:name20000:".to_vec();
+        long.extend(vec![b'x'; 20_000]);
+        long.extend(b"4:sizei-12ee");
+        assert_eq!(decode(&long), decode_from_reader(Trickle(&long, None)));
+        assert_eq!(Ok(BEncodingType::Integer(12)), decode_from_reader(&b"i12ei3e"[..]));
+
+        assert_eq!(Err(DecodingError::UnterminatedList { start_offset: 0 }),
+                   decode_from_reader(Trickle(b"li1e", None)));
+        assert_eq!(Err(DecodingError::EndOfFile), decode_from_reader(&b"99999999:abc"[..]));
+        assert_eq!(Err(DecodingError::Io(io::ErrorKind::ConnectionReset)),
+                   decode_from_reader(Trickle(b"li1e", Some(io::ErrorKind::ConnectionReset))));
+    }
+
+    #[test]
+    pub fn reset() {
+        let mut decoder = BDecoder::new(b"i1e");
+        assert_eq!(Ok(BEncodingType::Integer(1)), decoder.decode());
+        assert_eq!(Err(DecodingError::EndOfFile), decoder.decode());
+
+        decoder.reset(b"3:abc");
+        assert_eq!(decoder.cursor, 0);
+        assert_eq!(Ok(BEncodingType::String("abc".to_byte_string())), decoder.decode());
+
+        let mut decoder = BDecoder::new(b"i1e").with_budget(Some(Instant::now()), Some(CancelToken::new()));
+        assert_eq!(Err(DecodingError::TimedOut), decoder.decode());
+        decoder.reset(b"i2e");
+        assert_eq!(Ok(BEncodingType::Integer(2)), decoder.decode());
     }
 }
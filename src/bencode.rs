@@ -1,62 +1,265 @@
+use std::cmp::Ordering;
+use std::io::{self, Write};
+
 use linked_hash_map::LinkedHashMap;
 
 use crate::bdecode::BEncodingType;
 use crate::bytestring::ByteString;
+use crate::index::PathSegment;
+
+pub fn encode(bencoded: BEncodingType) -> Vec<u8> {
+    encode_ref(&bencoded)
+}
 
-pub(crate) fn encode(bencoded: BEncodingType) -> Vec<u8> {
-    // TODO: Don't use vec. Try to find a bytes writer
+// Same as encode, but leaves the value with the caller
+pub fn encode_ref(bencoded: &BEncodingType) -> Vec<u8> {
     let mut buf = Vec::new();
     encode_type(bencoded, &mut buf);
     buf
 }
 
-fn encode_type(bencoding: BEncodingType, buf: &mut Vec<u8>) {
+// Writes `bencoded` in insertion order straight to `writer`, returning the
+// number of bytes written. Wrap unbuffered writers in a BufWriter, as this
+// issues many small writes.
+pub fn encode_to_writer(bencoded: &BEncodingType, writer: &mut impl Write) -> io::Result<usize> {
+    write_type(bencoded, writer)
+}
+
+// Encodes with dictionary keys sorted by their raw bytes, as the spec requires
+pub fn encode_sorted(bencoded: BEncodingType) -> Vec<u8> {
+    encode_sorted_ref(&bencoded)
+}
+
+// Same as encode_sorted, but leaves the value with the caller
+pub fn encode_sorted_ref(bencoded: &BEncodingType) -> Vec<u8> {
+    encode_sorted_by_ref(bencoded, |a, b| a.0.cmp(&b.0))
+}
+
+// Encodes with dictionary keys sorted by a custom comparator, for dialects that
+// order keys differently
+pub fn encode_sorted_by<F>(bencoded: BEncodingType, cmp: F) -> Vec<u8>
+    where F: Fn(&ByteString, &ByteString) -> Ordering {
+    encode_sorted_by_ref(&bencoded, cmp)
+}
+
+// Same as encode_sorted_by, but leaves the value with the caller
+pub fn encode_sorted_by_ref<F>(bencoded: &BEncodingType, cmp: F) -> Vec<u8>
+    where F: Fn(&ByteString, &ByteString) -> Ordering {
+    let mut buf = Vec::new();
+    write_sorted(bencoded, &cmp, &mut buf).expect("writing to a Vec cannot fail");
+    buf
+}
+
+// Outcome of encoding one value with each available strategy
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EncodingComparison<'a> {
+    pub insertion_order: Vec<u8>,
+    pub canonical: Vec<u8>,
+    // Offset of the first byte where the two encodings differ
+    pub first_difference: Option<usize>,
+    // Paths of the dictionaries whose keys are not in canonical order, such as
+    // ["info", "files", 0]. The root is the empty path.
+    pub reordered: Vec<Vec<PathSegment<'a>>>,
+}
+
+// Encodes `value` both in insertion order and canonically, and reports where
+// they diverge. A non-empty `reordered` is the usual reason an infohash
+// changes after a decode and encode round trip.
+//
+// There is no fidelity strategy to compare against. Decoding keeps neither
+// the spelling of integers such as "i01e" nor duplicate keys, so no encoder
+// can reproduce such input from a BEncodingType. Compare the original bytes
+// with `insertion_order` to find those differences.
+pub fn compare_encodings(value: &BEncodingType) -> EncodingComparison<'_> {
+    let insertion_order = encode_ref(value);
+    let canonical = encode_sorted_ref(value);
+    // Both hold the same entries, only in a different order, so lengths match
+    let first_difference = insertion_order.iter().zip(&canonical).position(|(a, b)| a != b);
+    let mut reordered = Vec::new();
+    find_unsorted(value, &mut Vec::new(), &mut reordered);
+    EncodingComparison { insertion_order, canonical, first_difference, reordered }
+}
+
+fn find_unsorted<'a>(value: &'a BEncodingType, path: &mut Vec<PathSegment<'a>>, out: &mut Vec<Vec<PathSegment<'a>>>) {
+    match value {
+        BEncodingType::List(list) => {
+            for (i, item) in list.iter().enumerate() {
+                path.push(PathSegment::Index(i));
+                find_unsorted(item, path, out);
+                path.pop();
+            }
+        }
+        BEncodingType::Dictionary(dict) => {
+            if dict.keys().zip(dict.keys().skip(1)).any(|(a, b)| a.0 > b.0) {
+                out.push(path.clone());
+            }
+            for (key, item) in dict.iter() {
+                path.push(PathSegment::Key(&key.0));
+                find_unsorted(item, path, out);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn write_type<W: Write>(bencoding: &BEncodingType, writer: &mut W) -> io::Result<usize> {
     match bencoding {
-        BEncodingType::Integer(int) => { encode_int(int, buf); }
-        BEncodingType::String(bytes) => { encode_bytestring(bytes, buf) }
-        BEncodingType::List(list) => { encode_list(list, buf) }
-        BEncodingType::Dictionary(dict) => { encode_dict(dict, buf) }
-    };
+        BEncodingType::Integer(int) => write_int(*int, writer),
+        BEncodingType::String(bytes) => write_bytestring(bytes, writer),
+        BEncodingType::List(list) => write_list(list, writer),
+        BEncodingType::Dictionary(dict) => write_dict(dict, writer),
+    }
+}
+
+// Like write_type, but writes each dictionary's entries ordered by `cmp`
+fn write_sorted<W: Write, F>(bencoding: &BEncodingType, cmp: &F, writer: &mut W) -> io::Result<usize>
+    where F: Fn(&ByteString, &ByteString) -> Ordering {
+    match bencoding {
+        BEncodingType::List(list) => {
+            let mut written = write_all(writer, b"l")?;
+            for item in list {
+                written += write_sorted(item, cmp, writer)?;
+            }
+            Ok(written + write_all(writer, b"e")?)
+        }
+        BEncodingType::Dictionary(dict) => {
+            let mut entries: Vec<_> = dict.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| cmp(a, b));
+            let mut written = write_all(writer, b"d")?;
+            for (key, val) in entries {
+                written += write_bytestring(key, writer)?;
+                written += write_sorted(val, cmp, writer)?;
+            }
+            Ok(written + write_all(writer, b"e")?)
+        }
+        scalar => write_type(scalar, writer),
+    }
 }
 
-fn encode_dict(dict: LinkedHashMap<ByteString, BEncodingType>, buf: &mut Vec<u8>) {
-    buf.push(b'd');
-    for (key, val) in dict.into_iter() {
-        encode_bytestring(key, buf);
-        encode_type(val, buf);
+fn write_dict<W: Write>(dict: &LinkedHashMap<ByteString, BEncodingType>, writer: &mut W) -> io::Result<usize> {
+    let mut written = write_all(writer, b"d")?;
+    for (key, val) in dict.iter() {
+        written += write_bytestring(key, writer)?;
+        written += write_type(val, writer)?;
     }
-    buf.push(b'e');
+    Ok(written + write_all(writer, b"e")?)
 }
 
-fn encode_list(list: Vec<BEncodingType>, buf: &mut Vec<u8>) {
-    buf.push(b'l');
+fn write_list<W: Write>(list: &[BEncodingType], writer: &mut W) -> io::Result<usize> {
+    let mut written = write_all(writer, b"l")?;
     for item in list {
-        encode_type(item, buf);
+        written += write_type(item, writer)?;
     }
-    buf.push(b'e')
+    Ok(written + write_all(writer, b"e")?)
 }
 
-fn encode_bytestring(bs: ByteString, buf: &mut Vec<u8>) {
-    encode_num(bs.0.len() as i64, buf);
-    buf.push(b':');
-    buf.extend(bs.0.iter());
+fn write_bytestring<W: Write>(bs: &ByteString, writer: &mut W) -> io::Result<usize> {
+    Ok(write_num(bs.0.len() as u64, false, writer)? + write_all(writer, b":")? + write_all(writer, &bs.0)?)
 }
 
-fn encode_int(int: i64, buf: &mut Vec<u8>) {
-    buf.push(b'i');
-    // FIXME: Performance is likely slow
-    encode_num(int, buf);
-    buf.push(b'e');
+fn write_int<W: Write>(int: i64, writer: &mut W) -> io::Result<usize> {
+    Ok(write_all(writer, b"i")? + write_num(int.unsigned_abs(), int < 0, writer)? + write_all(writer, b"e")?)
+}
+
+// Formats the digits on the stack instead of going through to_string, as
+// every string length and integer passes through here
+fn write_num<W: Write>(mut num: u64, negative: bool, writer: &mut W) -> io::Result<usize> {
+    // u64::MAX has 20 digits, plus one for the sign
+    let mut digits = [0u8; 21];
+    let mut start = digits.len();
+    loop {
+        start -= 1;
+        digits[start] = b'0' + (num % 10) as u8;
+        num /= 10;
+        if num == 0 {
+            break;
+        }
+    }
+    if negative {
+        start -= 1;
+        digits[start] = b'-';
+    }
+    write_all(writer, &digits[start..])
 }
 
-fn encode_num(int: i64, buf: &mut Vec<u8>) {
-    buf.extend(int.to_string().bytes());
+fn write_all<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<usize> {
+    writer.write_all(bytes).map(|_| bytes.len())
+}
+
+// The Vec based helpers below go through the write_ functions, so there is a
+// single serialization routine. Writing to a Vec cannot fail.
+fn encode_type(bencoding: &BEncodingType, buf: &mut Vec<u8>) {
+    match bencoding {
+        BEncodingType::Integer(int) => { encode_int(*int, buf); }
+        BEncodingType::String(bytes) => { encode_bytestring(bytes, buf) }
+        BEncodingType::List(list) => { encode_list(list, buf) }
+        BEncodingType::Dictionary(dict) => { encode_dict(dict, buf) }
+    };
+}
+
+fn encode_dict(dict: &LinkedHashMap<ByteString, BEncodingType>, buf: &mut Vec<u8>) {
+    write_dict(dict, buf).expect("writing to a Vec cannot fail");
+}
+
+fn encode_list(list: &[BEncodingType], buf: &mut Vec<u8>) {
+    write_list(list, buf).expect("writing to a Vec cannot fail");
+}
+
+fn encode_bytestring(bs: &ByteString, buf: &mut Vec<u8>) {
+    write_bytestring(bs, buf).expect("writing to a Vec cannot fail");
+}
+
+fn encode_int(int: i64, buf: &mut Vec<u8>) {
+    write_int(int, buf).expect("writing to a Vec cannot fail");
 }
 
 #[cfg(test)]
 mod test {
+    use crate::bdecode::{decode, read_int_at};
+
     use super::*;
 
+    #[test]
+    fn encode_to_writer_matches_encode() {
+        let inp = b"d4:infod5:filesld6:lengthi-1e4:pathl1:aeee4:name0:e1:ali1ei2eee";
+        let value = decode(inp).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(inp.len(), encode_to_writer(&value, &mut out).unwrap());
+        assert_eq!(encode_ref(&value), out);
+        assert_eq!(encode(value), out);
+
+        let mut full = [0u8; 4];
+        assert_eq!(io::ErrorKind::WriteZero,
+                   encode_to_writer(&decode(b"3:abc").unwrap(), &mut full.as_mut_slice()).unwrap_err().kind());
+    }
+
+    #[test]
+    fn compare_encodings_reports_unsorted_dicts() {
+        let value = decode(b"d4:infod5:filesld6:lengthi1e4:pathl1:aeed4:pathl1:be6:lengthi2eee4:name1:xe1:ai1ee").unwrap();
+        let report = compare_encodings(&value);
+        assert_eq!(b"d4:infod5:filesld6:lengthi1e4:pathl1:aeed4:pathl1:be6:lengthi2eee4:name1:xe1:ai1ee".to_vec(),
+                   report.insertion_order);
+        assert_eq!(b"d1:ai1e4:infod5:filesld6:lengthi1e4:pathl1:aeed6:lengthi2e4:pathl1:beee4:name1:xee".to_vec(),
+                   report.canonical);
+        assert_eq!(Some(1), report.first_difference);
+        let files: Vec<PathSegment> = vec!["info".into(), "files".into(), 1.into()];
+        assert_eq!(vec![vec![], files], report.reordered);
+        // Paths borrow from the value and work with the path based lookups
+        let length = [report.reordered[1].as_slice(), &["length".into()]].concat();
+        assert_eq!(Ok(Some(2)), read_int_at(&report.insertion_order, &length));
+
+        // Keys stay raw bytes, so neither dots nor invalid UTF-8 are ambiguous
+        let value = decode(b"d1:\xffd3:a.bd1:bi1e1:ai2eeee").unwrap();
+        assert_eq!(vec![vec![PathSegment::Key(b"\xff"), PathSegment::Key(b"a.b")]], compare_encodings(&value).reordered);
+
+        let value = decode(b"d1:ai1e1:bli2eee").unwrap();
+        let sorted = compare_encodings(&value);
+        assert_eq!(sorted.insertion_order, sorted.canonical);
+        assert_eq!(None, sorted.first_difference);
+        assert!(sorted.reordered.is_empty());
+    }
+
     #[test]
     fn encode_int_zero() {
         let mut v = Vec::new();
@@ -87,29 +290,41 @@ mod test {
         assert_eq!(b"i-123ei-45ei67e".to_vec(), v);
     }
 
+    #[test]
+    fn encode_int_extremes() {
+        let mut v = Vec::new();
+        encode_int(i64::MIN, &mut v);
+        assert_eq!(b"i-9223372036854775808e".to_vec(), v);
+
+        v.clear();
+        encode_int(i64::MAX, &mut v);
+        assert_eq!(b"i9223372036854775807e".to_vec(), v);
+    }
+
     #[test]
     fn test_encode_bytestring() {
         let mut v = Vec::new();
-        encode_bytestring(ByteString(b"abcd".to_vec()), &mut v);
+        encode_bytestring(&ByteString(b"abcd".to_vec()), &mut v);
         assert_eq!(b"4:abcd".to_vec(), v);
 
-        encode_bytestring(ByteString(b"123".to_vec()), &mut v);
+        encode_bytestring(&ByteString(b"123".to_vec()), &mut v);
         assert_eq!(b"4:abcd3:123".to_vec(), v);
 
-        encode_bytestring(ByteString(b"\n\r\t\\/,".to_vec()), &mut v);
+        encode_bytestring(&ByteString(b"\n\r\t\\/,".to_vec()), &mut v);
         assert_eq!(b"4:abcd3:1236:\n\r\t\\/,".to_vec(), v);
     }
 
     #[test]
     fn encode_list_empty() {
         let mut v = Vec::new();
-        encode_list(Vec::new(), &mut v);
+        encode_list(&[], &mut v);
         assert_eq!(b"le".to_vec(), v);
     }
 
+    #[test]
     fn encode_list_flat() {
         let mut v = Vec::new();
-        encode_list(vec![
+        encode_list(&[
             BEncodingType::String(ByteString(b"abc".to_vec())),
             BEncodingType::Integer(345),
             BEncodingType::String(ByteString(b"def".to_vec())),
@@ -120,7 +335,7 @@ mod test {
     #[test]
     fn encode_list_inner() {
         let mut v = Vec::new();
-        encode_list(vec![
+        encode_list(&[
             BEncodingType::Integer(345),
             BEncodingType::List(vec![
                 BEncodingType::String(ByteString(b"inner".to_vec())),
@@ -138,7 +353,7 @@ mod test {
     #[test]
     fn encode_dict_empty() {
         let mut v = Vec::new();
-        encode_dict(LinkedHashMap::new(), &mut v);
+        encode_dict(&LinkedHashMap::new(), &mut v);
         assert_eq!(b"de".to_vec(), v);
     }
 
@@ -148,7 +363,7 @@ mod test {
         let mut dict = LinkedHashMap::new();
         dict.insert(ByteString(b"item1".to_vec()), BEncodingType::Integer(123));
         dict.insert(ByteString(b"item2".to_vec()), BEncodingType::String(ByteString(b"value".to_vec())));
-        encode_dict(dict, &mut v);
+        encode_dict(&dict, &mut v);
         assert_eq!(b"d5:item1i123e5:item25:valuee".to_vec(), v);
     }
 
@@ -167,7 +382,35 @@ mod test {
 
         dict.insert(ByteString(b"inner".to_vec()), BEncodingType::Dictionary(inner_dict));
 
-        encode_dict(dict, &mut v);
+        encode_dict(&dict, &mut v);
         assert_eq!(b"d5:item1i123e5:item25:value5:innerd10:inneritem1i888e10:inneritem2d4:corei50000eeee".to_vec(), v);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn encode_sorted_orders_keys_by_raw_bytes() {
+        let mut inner = LinkedHashMap::new();
+        inner.insert(ByteString(b"b".to_vec()), BEncodingType::Integer(2));
+        inner.insert(ByteString(b"a".to_vec()), BEncodingType::Integer(1));
+        let mut dict = LinkedHashMap::new();
+        dict.insert(ByteString(b"z".to_vec()), BEncodingType::List(vec![BEncodingType::Dictionary(inner)]));
+        dict.insert(ByteString(b"B".to_vec()), BEncodingType::Integer(3));
+        dict.insert(ByteString(b"a".to_vec()), BEncodingType::Integer(4));
+
+        let value = BEncodingType::Dictionary(dict);
+        assert_eq!(b"d1:Bi3e1:ai4e1:zld1:ai1e1:bi2eeee".to_vec(), encode_sorted_ref(&value));
+        assert_eq!(encode_sorted_ref(&value), encode_sorted(value));
+    }
+
+    #[test]
+    fn encode_sorted_by_uses_comparator() {
+        let mut dict = LinkedHashMap::new();
+        dict.insert(ByteString(b"b".to_vec()), BEncodingType::Integer(1));
+        dict.insert(ByteString(b"C".to_vec()), BEncodingType::Integer(2));
+        dict.insert(ByteString(b"a".to_vec()), BEncodingType::Integer(3));
+
+        let encoded = encode_sorted_by(BEncodingType::Dictionary(dict), |a, b| {
+            a.0.to_ascii_lowercase().cmp(&b.0.to_ascii_lowercase())
+        });
+        assert_eq!(b"d1:ai3e1:bi1e1:Ci2ee".to_vec(), encoded);
+    }
+}
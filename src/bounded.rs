@@ -0,0 +1,116 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::bdecode::{BDecoder, BEncodingType};
+use crate::error::DecodingError;
+
+type Result<T> = std::result::Result<T, DecodingError>;
+
+// Shared flag for aborting a running operation from another thread
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancelToken {
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        CancelToken(flag)
+    }
+}
+
+// Decoder for untrusted input with per-message budgets. Oversized input is
+// rejected before any parsing, and the time budget and cancel token are checked
+// before every value.
+#[derive(Debug, Clone)]
+pub struct BoundedDecoder {
+    max_bytes: usize,
+    timeout: Option<Duration>,
+    cancel: Option<CancelToken>,
+}
+
+impl BoundedDecoder {
+    pub fn new(max_bytes: usize) -> BoundedDecoder {
+        BoundedDecoder { max_bytes, timeout: None, cancel: None }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    pub fn decode(&self, inp: &[u8]) -> Result<BEncodingType> {
+        if inp.len() > self.max_bytes {
+            return Err(DecodingError::SizeLimitExceeded(self.max_bytes));
+        }
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        BDecoder::new(inp)
+            .with_budget(deadline, self.cancel.clone())
+            .decode()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decodes_within_budget() {
+        let decoder = BoundedDecoder::new(16)
+            .timeout(Duration::from_secs(60))
+            .cancel_token(CancelToken::new());
+        assert_eq!(Ok(BEncodingType::List(vec![BEncodingType::Integer(1)])), decoder.decode(b"li1ee"));
+    }
+
+    #[test]
+    fn rejects_oversized_input() {
+        let decoder = BoundedDecoder::new(4);
+        assert_eq!(Ok(BEncodingType::Integer(10)), decoder.decode(b"i10e"));
+        assert_eq!(Err(DecodingError::SizeLimitExceeded(4)), decoder.decode(b"i100e"));
+    }
+
+    #[test]
+    fn stops_when_out_of_time() {
+        let decoder = BoundedDecoder::new(16).timeout(Duration::ZERO);
+        assert_eq!(Err(DecodingError::TimedOut), decoder.decode(b"li1ee"));
+    }
+
+    #[test]
+    fn reports_timeout_inside_nested_dictionaries() {
+        // Far more items than can be decoded in a millisecond, all below two keys
+        let mut inp = b"d1:ad1:bl".to_vec();
+        for _ in 0..1_000_000 {
+            inp.extend(b"i1e");
+        }
+        inp.extend(b"eee");
+        let decoder = BoundedDecoder::new(inp.len()).timeout(Duration::from_millis(1));
+        assert_eq!(Err(DecodingError::TimedOut), decoder.decode(&inp));
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let decoder = BoundedDecoder::new(16).cancel_token(CancelToken::from(flag.clone()));
+        assert_eq!(Ok(BEncodingType::Integer(1)), decoder.decode(b"i1e"));
+
+        flag.store(true, Ordering::Relaxed);
+        assert_eq!(Err(DecodingError::Cancelled), decoder.decode(b"i1e"));
+    }
+}
@@ -0,0 +1,34 @@
+// Oldest toolchain the crate is supported on. Matches `rust-version` in Cargo.toml.
+pub const MSRV: &str = "1.76";
+
+// Reports which optional code paths were compiled into this build.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    // Whether the `unsafe-opt` feature lifted the crate-wide unsafe_code ban
+    pub unsafe_opt: bool,
+}
+
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        unsafe_opt: cfg!(feature = "unsafe-opt"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reports_compiled_paths() {
+        let info = build_info();
+        assert_eq!(env!("CARGO_PKG_VERSION"), info.version);
+        assert_eq!(cfg!(feature = "unsafe-opt"), info.unsafe_opt);
+    }
+
+    #[test]
+    fn msrv_matches_manifest() {
+        assert_eq!(env!("CARGO_PKG_RUST_VERSION"), MSRV);
+    }
+}
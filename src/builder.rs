@@ -0,0 +1,118 @@
+// Fluent construction of dictionaries and lists:
+//
+//     DictBuilder::new().int("length", 42).bytes("pieces", pieces).build()
+//
+// Dictionary entries keep the order they are added in. Adding a key twice
+// keeps the last value, at the position of the last insert, just like decode
+// does for duplicate keys.
+
+use crate::bdecode::BEncodingType;
+use crate::bytestring::ByteString;
+use crate::convert::{Dict, ToBencode};
+
+#[derive(Debug, Clone, Default)]
+pub struct DictBuilder {
+    dict: Dict,
+}
+
+impl DictBuilder {
+    pub fn new() -> DictBuilder {
+        DictBuilder::default()
+    }
+
+    pub fn int(self, key: impl AsRef<[u8]>, value: i64) -> Self {
+        self.value(key, BEncodingType::Integer(value))
+    }
+
+    pub fn bytes(self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Self {
+        self.value(key, BEncodingType::String(ByteString(value.as_ref().to_vec())))
+    }
+
+    pub fn list(self, key: impl AsRef<[u8]>, list: ListBuilder) -> Self {
+        self.value(key, list.build())
+    }
+
+    pub fn dict(self, key: impl AsRef<[u8]>, dict: DictBuilder) -> Self {
+        self.value(key, dict.build())
+    }
+
+    // Anything with a bencode representation, such as a nested value or a
+    // type implementing ToBencode
+    pub fn value(mut self, key: impl AsRef<[u8]>, value: impl ToBencode) -> Self {
+        self.dict.insert(ByteString(key.as_ref().to_vec()), value.to_bencode());
+        self
+    }
+
+    pub fn build(self) -> BEncodingType {
+        BEncodingType::Dictionary(self.dict)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListBuilder {
+    list: Vec<BEncodingType>,
+}
+
+impl ListBuilder {
+    pub fn new() -> ListBuilder {
+        ListBuilder::default()
+    }
+
+    pub fn int(self, value: i64) -> Self {
+        self.value(BEncodingType::Integer(value))
+    }
+
+    pub fn bytes(self, value: impl AsRef<[u8]>) -> Self {
+        self.value(BEncodingType::String(ByteString(value.as_ref().to_vec())))
+    }
+
+    pub fn list(self, list: ListBuilder) -> Self {
+        self.value(list.build())
+    }
+
+    pub fn dict(self, dict: DictBuilder) -> Self {
+        self.value(dict.build())
+    }
+
+    pub fn value(mut self, value: impl ToBencode) -> Self {
+        self.list.push(value.to_bencode());
+        self
+    }
+
+    pub fn build(self) -> BEncodingType {
+        BEncodingType::List(self.list)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bdecode::decode;
+
+    use super::*;
+
+    #[test]
+    fn builds_nested_values() {
+        let pieces = vec![0u8; 3];
+        let value = DictBuilder::new()
+            .bytes("announce", "http://tracker/announce")
+            .dict("info", DictBuilder::new()
+                .int("length", 42)
+                .bytes(b"pieces", &pieces)
+                .list("files", ListBuilder::new().int(1).bytes("a").list(ListBuilder::new()).dict(DictBuilder::new())))
+            .value("creation date", 1700000000i64)
+            .value("raw", decode(b"li1ee").unwrap())
+            .build();
+
+        let expected = decode(b"d8:announce23:http://tracker/announce4:infod6:lengthi42e6:pieces3:\x00\x00\x00\
+                                5:filesli1e1:aledeee13:creation datei1700000000e3:rawli1eee").unwrap();
+        assert_eq!(expected, value);
+    }
+
+    #[test]
+    fn repeated_keys_keep_last_value() {
+        let value = DictBuilder::new().int("a", 1).int("b", 2).int("a", 3).build();
+        assert_eq!(decode(b"d1:ai1e1:bi2e1:ai3ee").unwrap(), value);
+        assert_eq!(b"d1:bi2e1:ai3ee".to_vec(), crate::bencode::encode(value));
+        assert_eq!(BEncodingType::List(vec![]), ListBuilder::new().build());
+    }
+}
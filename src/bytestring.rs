@@ -1,9 +1,28 @@
+use std::borrow::Borrow;
 use std::fmt::Display;
 
+use crate::error::ConvertError;
+
 // Custom ByteString wrapper to avoid String allocations.
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct ByteString(pub Vec<u8>);
 
+impl ByteString {
+    // Copies the string into a fixed-size array, for info hashes, peer ids and
+    // other fields with a known length
+    pub fn as_array<const N: usize>(&self) -> Result<[u8; N], ConvertError> {
+        self.0.as_slice().try_into().or(Err(ConvertError::InvalidLength { expected: N, found: self.0.len() }))
+    }
+}
+
+// Vec<u8> and [u8] hash alike, so maps keyed by ByteString can be queried
+// with plain byte slices
+impl Borrow<[u8]> for ByteString {
+    fn borrow(&self) -> &[u8] {
+        &self.0
+    }
+}
+
 pub trait ToByteString {
     fn to_byte_string(&self) -> ByteString;
 }
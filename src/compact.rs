@@ -0,0 +1,192 @@
+// Compact peer format shared by trackers (BEP 23, BEP 7) and the DHT: the
+// address in network byte order followed by the port, big-endian. Everything
+// here goes through explicit big-endian conversions so the packing never
+// depends on the byte order of the host.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+
+use crate::bdecode::BEncodingType;
+use crate::bytestring::ByteString;
+use crate::convert::{FromBencode, ToBencode};
+use crate::error::ConvertError;
+
+pub const PEER_V4_LEN: usize = 6;
+pub const PEER_V6_LEN: usize = 18;
+
+pub fn write_peer_v4(out: &mut Vec<u8>, addr: &SocketAddrV4) {
+    out.extend(u32::from(*addr.ip()).to_be_bytes());
+    out.extend(addr.port().to_be_bytes());
+}
+
+pub fn write_peer_v6(out: &mut Vec<u8>, addr: &SocketAddrV6) {
+    out.extend(u128::from(*addr.ip()).to_be_bytes());
+    out.extend(addr.port().to_be_bytes());
+}
+
+pub fn read_peer_v4(bytes: &[u8; PEER_V4_LEN]) -> SocketAddrV4 {
+    let [a, b, c, d, p0, p1] = *bytes;
+    SocketAddrV4::new(Ipv4Addr::from(u32::from_be_bytes([a, b, c, d])), u16::from_be_bytes([p0, p1]))
+}
+
+pub fn read_peer_v6(bytes: &[u8; PEER_V6_LEN]) -> SocketAddrV6 {
+    let mut ip = [0; 16];
+    ip.copy_from_slice(&bytes[..16]);
+    let port = u16::from_be_bytes([bytes[16], bytes[17]]);
+    SocketAddrV6::new(Ipv6Addr::from(u128::from_be_bytes(ip)), port, 0, 0)
+}
+
+// Splits a concatenation of compact IPv4 peers. Returns None if the length is
+// not a multiple of PEER_V4_LEN.
+pub fn peers_v4(bytes: &[u8]) -> Option<Vec<SocketAddrV4>> {
+    if bytes.len() % PEER_V4_LEN != 0 {
+        return None;
+    }
+    Some(bytes.chunks_exact(PEER_V4_LEN).map(|chunk| read_peer_v4(chunk.try_into().expect("exact chunk"))).collect())
+}
+
+// Like peers_v4, for the 18 byte IPv6 form
+pub fn peers_v6(bytes: &[u8]) -> Option<Vec<SocketAddrV6>> {
+    if bytes.len() % PEER_V6_LEN != 0 {
+        return None;
+    }
+    Some(bytes.chunks_exact(PEER_V6_LEN).map(|chunk| read_peer_v6(chunk.try_into().expect("exact chunk"))).collect())
+}
+
+// How an address was written in the document
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum IpRepr {
+    // "1.2.3.4" or "::1"
+    Text,
+    // 4 or 16 bytes in network order
+    Binary,
+}
+
+// An `ip`, `external ip` or `yourip` style field. Trackers and DHT nodes use
+// both textual and binary addresses for these, so parsing accepts either and
+// encoding writes the address back the way it came. Text is always written
+// in canonical form, so "::0001" comes back as "::1".
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct IpField {
+    pub addr: IpAddr,
+    pub repr: IpRepr,
+}
+
+impl IpField {
+    // Text wins for the rare strings that are valid both ways, such as the
+    // four bytes "1::1"
+    pub fn parse(bytes: &[u8]) -> Option<IpField> {
+        let text = std::str::from_utf8(bytes).ok().and_then(|text| text.parse().ok());
+        if let Some(addr) = text {
+            return Some(IpField { addr, repr: IpRepr::Text });
+        }
+        let addr = match bytes.len() {
+            4 => IpAddr::V4(Ipv4Addr::from(u32::from_be_bytes(bytes.try_into().expect("4 bytes")))),
+            16 => IpAddr::V6(Ipv6Addr::from(u128::from_be_bytes(bytes.try_into().expect("16 bytes")))),
+            _ => return None,
+        };
+        Some(IpField { addr, repr: IpRepr::Binary })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match (self.repr, self.addr) {
+            (IpRepr::Text, addr) => addr.to_string().into_bytes(),
+            (IpRepr::Binary, IpAddr::V4(ip)) => u32::from(ip).to_be_bytes().to_vec(),
+            (IpRepr::Binary, IpAddr::V6(ip)) => u128::from(ip).to_be_bytes().to_vec(),
+        }
+    }
+}
+
+impl ToBencode for IpField {
+    fn to_bencode(&self) -> BEncodingType {
+        BEncodingType::String(ByteString(self.to_bytes()))
+    }
+}
+
+impl FromBencode for IpField {
+    fn from_bencode(value: BEncodingType) -> Result<Self, ConvertError> {
+        let bytes = ByteString::from_bencode(value)?;
+        IpField::parse(&bytes.0).ok_or_else(|| ConvertError::Custom(format!("Invalid IP address '{}'", bytes)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::{Xorshift, SEED};
+
+    use super::*;
+
+    // Byte layouts spelled out by hand, so a host dependent conversion would
+    // fail on any target
+    #[test]
+    fn packs_in_network_order() {
+        let mut out = Vec::new();
+        write_peer_v4(&mut out, &"1.2.3.4:258".parse().unwrap());
+        assert_eq!(vec![1, 2, 3, 4, 1, 2], out);
+        assert_eq!("1.2.3.4:258".parse::<SocketAddrV4>().unwrap(), read_peer_v4(&[1, 2, 3, 4, 1, 2]));
+
+        let mut out = Vec::new();
+        write_peer_v6(&mut out, &"[102:304:506:708:90a:b0c:d0e:f10]:4370".parse().unwrap());
+        let expected: Vec<u8> = (1..=16).chain([0x11, 0x12]).collect();
+        assert_eq!(expected, out);
+        assert_eq!("[102:304:506:708:90a:b0c:d0e:f10]:4370".parse::<SocketAddrV6>().unwrap(),
+                   read_peer_v6(expected.as_slice().try_into().unwrap()));
+    }
+
+    #[test]
+    fn parses_ip_fields_in_any_form() {
+        let forms: &[(&[u8], &str, IpRepr)] = &[
+            (b"10.0.0.1", "10.0.0.1", IpRepr::Text),
+            (b"2001:db8::1", "2001:db8::1", IpRepr::Text),
+            (b"1::1", "1::1", IpRepr::Text),
+            (&[10, 0, 0, 1], "10.0.0.1", IpRepr::Binary),
+            (&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1], "2001:db8::1", IpRepr::Binary),
+        ];
+        for &(bytes, addr, repr) in forms {
+            let field = IpField::parse(bytes).unwrap();
+            assert_eq!(IpField { addr: addr.parse().unwrap(), repr }, field);
+            assert_eq!(bytes, field.to_bytes());
+            assert_eq!(Ok(field), IpField::from_bencode(field.to_bencode()));
+        }
+
+        assert_eq!(None, IpField::parse(b"tracker.example"));
+        assert_eq!(None, IpField::parse(&[1, 2, 3]));
+        assert_eq!(b"::1".to_vec(), IpField::parse(b"::0001").unwrap().to_bytes());
+        assert!(IpField::from_bencode(BEncodingType::Integer(1)).is_err());
+    }
+
+    #[test]
+    fn rejects_partial_peers() {
+        assert_eq!(Some(vec![]), peers_v4(b""));
+        assert_eq!(None, peers_v4(&[0; 7]));
+        assert_eq!(None, peers_v6(&[0; PEER_V4_LEN]));
+        assert_eq!(Some(vec![SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0); 2]), peers_v6(&[0; 2 * PEER_V6_LEN]));
+    }
+
+    #[test]
+    fn round_trips_random_addresses() {
+        let mut rng = Xorshift::new(SEED);
+        let mut next = || rng.next_u64();
+        for _ in 0..200 {
+            let count = (next() % 8) as usize;
+            let v4: Vec<SocketAddrV4> = (0..count)
+                .map(|_| SocketAddrV4::new(Ipv4Addr::from(next() as u32), next() as u16))
+                .collect();
+            let v6: Vec<SocketAddrV6> = (0..count)
+                .map(|_| {
+                    let ip = (next() as u128) << 64 | next() as u128;
+                    SocketAddrV6::new(Ipv6Addr::from(ip), next() as u16, 0, 0)
+                })
+                .collect();
+
+            let mut packed = Vec::new();
+            v4.iter().for_each(|addr| write_peer_v4(&mut packed, addr));
+            assert_eq!(count * PEER_V4_LEN, packed.len());
+            assert_eq!(Some(v4), peers_v4(&packed));
+
+            let mut packed = Vec::new();
+            v6.iter().for_each(|addr| write_peer_v6(&mut packed, addr));
+            assert_eq!(count * PEER_V6_LEN, packed.len());
+            assert_eq!(Some(v6), peers_v6(&packed));
+        }
+    }
+}
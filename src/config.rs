@@ -0,0 +1,95 @@
+// Bencode as an on-disk state format: `load` and `save` typed values with the
+// serde support, writing atomically so a crash never leaves a torn file.
+//
+// Loading is strict. Besides the usual decoding checks, the file must be the
+// canonical encoding of its contents, so files that were edited by hand or by
+// a sloppy tool are reported instead of being silently normalized on save.
+
+use std::fs;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::bdecode::decode;
+use crate::bencode::encode_sorted_ref;
+use crate::de::from_bytes;
+use crate::error::{ConfigError, SerdeError};
+use crate::io_util::write_atomic;
+use crate::ser::to_bytes;
+
+type Result<T> = std::result::Result<T, ConfigError>;
+
+pub fn load<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let bytes = fs::read(path).map_err(|err| ConfigError::Io(err.kind()))?;
+    let value = from_bytes(&bytes)?;
+    let canonical = decode(&bytes).map(|value| encode_sorted_ref(&value)).map_err(SerdeError::Decoding)?;
+    if canonical != bytes {
+        return Err(ConfigError::NotCanonical);
+    }
+    Ok(value)
+}
+
+// Replaces the file at `path` with the encoding of `value`
+pub fn save<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    let bytes = to_bytes(value)?;
+    write_atomic(path, &bytes, None).map_err(|err| ConfigError::Io(err.kind()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::io;
+    use std::path::PathBuf;
+
+    use serde::Deserialize;
+
+    use crate::error::DecodingError;
+
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct State {
+        version: u32,
+        torrents: BTreeMap<String, u64>,
+        paused: bool,
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("domenec-config-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn round_trips_state() {
+        let dir = scratch_dir("round-trip");
+        let path = dir.join("state.benc");
+        let state = State { version: 2, torrents: BTreeMap::from([("ubuntu".to_string(), 1024)]), paused: false };
+
+        save(&path, &state).unwrap();
+        assert_eq!(b"d6:pausedi0e8:torrentsd6:ubuntui1024ee7:versioni2ee".to_vec(), fs::read(&path).unwrap());
+        assert_eq!(Ok(state), load::<State>(&path));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let dir = scratch_dir("invalid");
+        let path = dir.join("state.benc");
+
+        assert_eq!(Err(ConfigError::Io(io::ErrorKind::NotFound)), load::<State>(&path));
+
+        fs::write(&path, b"d7:versioni2e6:pausedi0e8:torrentsdee").unwrap();
+        assert_eq!(Err(ConfigError::NotCanonical), load::<State>(&path));
+        fs::write(&path, b"d6:pausedi0e8:torrentsde7:versioni02ee").unwrap();
+        assert_eq!(Err(ConfigError::NotCanonical), load::<State>(&path));
+        fs::write(&path, b"d6:pausedi0e8:torrentsde7:versioni2eei1e").unwrap();
+        assert_eq!(Err(ConfigError::Serde(SerdeError::TrailingBytes(37))), load::<State>(&path));
+        fs::write(&path, b"d6:pausedi0e").unwrap();
+        assert_eq!(Err(ConfigError::Serde(SerdeError::Decoding(DecodingError::UnterminatedDictionary { start_offset: 0 }))),
+                   load::<State>(&path));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
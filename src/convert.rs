@@ -0,0 +1,381 @@
+// Conversions between application types and BEncodingType that don't need
+// serde. Implement ToBencode and FromBencode for a type to round-trip it
+// through the value tree and, from there, through encode and decode.
+//
+// Dictionary entries go through `to_field` and `from_field`, which let Option
+// values leave their key out. Outside a dictionary an Option is a list of zero
+// or one items.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::bdecode::BEncodingType;
+use crate::bytestring::{ByteString, ToByteString};
+use crate::error::ConvertError;
+
+type Result<T> = std::result::Result<T, ConvertError>;
+
+pub type Dict = LinkedHashMap<ByteString, BEncodingType>;
+
+pub trait ToBencode {
+    fn to_bencode(&self) -> BEncodingType;
+
+    // The value to store under a dictionary key, or None to leave it out
+    fn to_field(&self) -> Option<BEncodingType> {
+        Some(self.to_bencode())
+    }
+}
+
+pub trait FromBencode: Sized {
+    fn from_bencode(value: BEncodingType) -> Result<Self>;
+
+    // Builds the value stored under `key`, which is None if the key is missing
+    fn from_field(key: &[u8], value: Option<BEncodingType>) -> Result<Self> {
+        match value {
+            Some(value) => Self::from_bencode(value).map_err(|err| ConvertError::InvalidValueForKey {
+                key: key.to_byte_string(),
+                source: Box::new(err),
+            }),
+            None => Err(ConvertError::MissingKey(key.to_byte_string())),
+        }
+    }
+}
+
+// Types usable as dictionary keys
+pub trait BencodeKey: Sized {
+    fn to_key(&self) -> ByteString;
+    fn from_key(key: ByteString) -> Result<Self>;
+}
+
+fn unexpected(expected: &'static str, found: &BEncodingType) -> ConvertError {
+    ConvertError::UnexpectedType { expected, found: found.type_name() }
+}
+
+impl ToBencode for BEncodingType {
+    fn to_bencode(&self) -> BEncodingType {
+        self.clone()
+    }
+}
+
+impl<T: ToBencode + ?Sized> ToBencode for &T {
+    fn to_bencode(&self) -> BEncodingType {
+        T::to_bencode(self)
+    }
+
+    fn to_field(&self) -> Option<BEncodingType> {
+        T::to_field(self)
+    }
+}
+
+impl ToBencode for i64 {
+    fn to_bencode(&self) -> BEncodingType {
+        BEncodingType::Integer(*self)
+    }
+}
+
+impl FromBencode for i64 {
+    fn from_bencode(value: BEncodingType) -> Result<Self> {
+        match value {
+            BEncodingType::Integer(i) => Ok(i),
+            other => Err(unexpected("integer", &other)),
+        }
+    }
+}
+
+impl ToBencode for str {
+    fn to_bencode(&self) -> BEncodingType {
+        BEncodingType::String(self.to_byte_string())
+    }
+}
+
+impl ToBencode for String {
+    fn to_bencode(&self) -> BEncodingType {
+        self.as_str().to_bencode()
+    }
+}
+
+impl FromBencode for String {
+    fn from_bencode(value: BEncodingType) -> Result<Self> {
+        String::from_key(ByteString::from_bencode(value)?)
+    }
+}
+
+impl ToBencode for ByteString {
+    fn to_bencode(&self) -> BEncodingType {
+        BEncodingType::String(self.clone())
+    }
+}
+
+impl FromBencode for ByteString {
+    fn from_bencode(value: BEncodingType) -> Result<Self> {
+        match value {
+            BEncodingType::String(s) => Ok(s),
+            other => Err(unexpected("string", &other)),
+        }
+    }
+}
+
+// Byte vectors are strings, not lists of integers
+impl ToBencode for Vec<u8> {
+    fn to_bencode(&self) -> BEncodingType {
+        BEncodingType::String(ByteString(self.clone()))
+    }
+}
+
+impl FromBencode for Vec<u8> {
+    fn from_bencode(value: BEncodingType) -> Result<Self> {
+        ByteString::from_bencode(value).map(|s| s.0)
+    }
+}
+
+// Fixed-size byte arrays are strings of exactly N bytes. As fields, a length
+// mismatch is reported under the key it was found at.
+impl<const N: usize> ToBencode for [u8; N] {
+    fn to_bencode(&self) -> BEncodingType {
+        BEncodingType::String(ByteString(self.to_vec()))
+    }
+}
+
+impl<const N: usize> FromBencode for [u8; N] {
+    fn from_bencode(value: BEncodingType) -> Result<Self> {
+        ByteString::from_bencode(value)?.as_array()
+    }
+}
+
+impl<T: ToBencode> ToBencode for Vec<T> {
+    fn to_bencode(&self) -> BEncodingType {
+        BEncodingType::List(self.iter().map(T::to_bencode).collect())
+    }
+}
+
+impl<T: FromBencode> FromBencode for Vec<T> {
+    fn from_bencode(value: BEncodingType) -> Result<Self> {
+        match value {
+            BEncodingType::List(list) => list.into_iter().map(T::from_bencode).collect(),
+            other => Err(unexpected("list", &other)),
+        }
+    }
+}
+
+impl<T: ToBencode> ToBencode for Option<T> {
+    fn to_bencode(&self) -> BEncodingType {
+        BEncodingType::List(self.iter().map(T::to_bencode).collect())
+    }
+
+    fn to_field(&self) -> Option<BEncodingType> {
+        self.as_ref().map(T::to_bencode)
+    }
+}
+
+impl<T: FromBencode> FromBencode for Option<T> {
+    fn from_bencode(value: BEncodingType) -> Result<Self> {
+        let mut list = match value {
+            BEncodingType::List(list) => list,
+            other => return Err(unexpected("list", &other)),
+        };
+        match list.len() {
+            0 | 1 => list.pop().map(T::from_bencode).transpose(),
+            found => Err(ConvertError::InvalidLength { expected: 1, found }),
+        }
+    }
+
+    fn from_field(key: &[u8], value: Option<BEncodingType>) -> Result<Self> {
+        match value {
+            Some(value) => T::from_field(key, Some(value)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl BencodeKey for ByteString {
+    fn to_key(&self) -> ByteString {
+        self.clone()
+    }
+
+    fn from_key(key: ByteString) -> Result<Self> {
+        Ok(key)
+    }
+}
+
+impl BencodeKey for String {
+    fn to_key(&self) -> ByteString {
+        self.as_str().to_byte_string()
+    }
+
+    fn from_key(key: ByteString) -> Result<Self> {
+        String::from_utf8(key.0).or(Err(ConvertError::NotUtf8))
+    }
+}
+
+impl BencodeKey for Vec<u8> {
+    fn to_key(&self) -> ByteString {
+        ByteString(self.clone())
+    }
+
+    fn from_key(key: ByteString) -> Result<Self> {
+        Ok(key.0)
+    }
+}
+
+// Maps without a meaningful order are `sorted` by key bytes, so they encode
+// canonically and the same on every run
+fn dict_to_bencode<'v, K, V, I>(entries: I, sorted: bool) -> BEncodingType
+    where K: BencodeKey + 'v, V: ToBencode + 'v, I: Iterator<Item = (&'v K, &'v V)> {
+    let mut entries: Vec<_> = entries
+        .filter_map(|(key, value)| value.to_field().map(|value| (key.to_key(), value)))
+        .collect();
+    if sorted {
+        entries.sort_by(|(a, _), (b, _)| a.0.cmp(&b.0));
+    }
+    BEncodingType::Dictionary(entries.into_iter().collect())
+}
+
+// Unwraps a dictionary, for FromBencode impls of dictionary-shaped types
+pub fn into_dict(value: BEncodingType) -> Result<Dict> {
+    match value {
+        BEncodingType::Dictionary(dict) => Ok(dict),
+        other => Err(unexpected("dictionary", &other)),
+    }
+}
+
+fn dict_from_bencode<K, V, C>(value: BEncodingType) -> Result<C>
+    where K: BencodeKey, V: FromBencode, C: FromIterator<(K, V)> {
+    into_dict(value)?.into_iter()
+        .map(|(key, value)| {
+            let parsed = V::from_field(&key.0, Some(value))?;
+            Ok((K::from_key(key)?, parsed))
+        })
+        .collect()
+}
+
+impl<K: BencodeKey, V: ToBencode> ToBencode for HashMap<K, V> {
+    fn to_bencode(&self) -> BEncodingType {
+        dict_to_bencode(self.iter(), true)
+    }
+}
+
+impl<K: BencodeKey + Eq + Hash, V: FromBencode> FromBencode for HashMap<K, V> {
+    fn from_bencode(value: BEncodingType) -> Result<Self> {
+        dict_from_bencode(value)
+    }
+}
+
+impl<K: BencodeKey + Eq + Hash, V: ToBencode> ToBencode for LinkedHashMap<K, V> {
+    fn to_bencode(&self) -> BEncodingType {
+        dict_to_bencode(self.iter(), false)
+    }
+}
+
+impl<K: BencodeKey + Eq + Hash, V: FromBencode> FromBencode for LinkedHashMap<K, V> {
+    fn from_bencode(value: BEncodingType) -> Result<Self> {
+        dict_from_bencode(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bdecode::decode;
+    use crate::bencode::{encode, encode_sorted};
+
+    use super::*;
+
+    fn round_trip<T: ToBencode + FromBencode>(value: &T) -> Result<T> {
+        T::from_bencode(decode(&encode(value.to_bencode())).unwrap())
+    }
+
+    #[test]
+    fn converts_scalars() {
+        assert_eq!(b"i-3e".to_vec(), encode((-3i64).to_bencode()));
+        assert_eq!(b"3:abc".to_vec(), encode("abc".to_bencode()));
+        assert_eq!(b"2:\xff\x00".to_vec(), encode(vec![0xffu8, 0].to_bencode()));
+        assert_eq!(Ok(7), round_trip(&7i64));
+        assert_eq!(Ok("abc".to_string()), round_trip(&"abc".to_string()));
+        assert_eq!(Ok("x".to_byte_string()), round_trip(&"x".to_byte_string()));
+
+        assert_eq!(Err(ConvertError::NotUtf8), String::from_bencode(decode(b"1:\xff").unwrap()));
+        assert_eq!(Err(ConvertError::UnexpectedType { expected: "integer", found: "string" }),
+                   i64::from_bencode(decode(b"1:a").unwrap()));
+    }
+
+    #[test]
+    fn converts_collections() {
+        let list = vec![vec![1i64, 2], vec![]];
+        assert_eq!(b"lli1ei2eelee".to_vec(), encode(list.to_bencode()));
+        assert_eq!(Ok(list), round_trip(&vec![vec![1i64, 2], vec![]]));
+
+        let mut dict = LinkedHashMap::new();
+        dict.insert("b".to_string(), Some(1i64));
+        dict.insert("a".to_string(), None);
+        dict.insert("c".to_string(), Some(3));
+        assert_eq!(b"d1:bi1e1:ci3ee".to_vec(), encode(dict.to_bencode()));
+        dict.remove("a");
+        assert_eq!(Ok(dict.clone()), round_trip(&dict));
+
+        let map: HashMap<Vec<u8>, String> = (0..32u8).rev().map(|i| (vec![b'a' + i], i.to_string())).collect();
+        let encoded = encode(map.to_bencode());
+        assert_eq!(encode_sorted(map.to_bencode()), encoded);
+        assert!(encoded.starts_with(b"d1:a1:01:b1:1"));
+        let map = HashMap::from([(b"y".to_vec(), "2".to_string()), (b"x".to_vec(), "1".to_string())]);
+        assert_eq!(b"d1:x1:11:y1:2e".to_vec(), encode(map.to_bencode()));
+        assert_eq!(Ok(map.clone()), round_trip(&map));
+
+        assert_eq!(Err(ConvertError::InvalidValueForKey {
+            key: "a".to_byte_string(),
+            source: Box::new(ConvertError::UnexpectedType { expected: "integer", found: "list" }),
+        }), HashMap::<String, i64>::from_bencode(decode(b"d1:alee").unwrap()));
+    }
+
+    #[test]
+    fn converts_fixed_size_strings() {
+        let info_hash = ByteString(vec![7; 20]);
+        assert_eq!(Ok([7; 20]), info_hash.as_array::<20>());
+        assert_eq!(Err(ConvertError::InvalidLength { expected: 32, found: 20 }), info_hash.as_array::<32>());
+        assert_eq!(Ok([7; 20]), round_trip(&[7u8; 20]));
+
+        let mut dict = into_dict(decode(b"d9:info hash3:abce").unwrap()).unwrap();
+        assert_eq!(Err(ConvertError::InvalidValueForKey {
+            key: "info hash".to_byte_string(),
+            source: Box::new(ConvertError::InvalidLength { expected: 20, found: 3 }),
+        }), <[u8; 20]>::from_field(b"info hash", dict.remove(&"info hash".to_byte_string())));
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derives_struct_conversions() {
+        use crate::{FromBencode, ToBencode};
+
+        #[derive(Debug, PartialEq, ToBencode, FromBencode)]
+        struct File {
+            length: i64,
+            path: Vec<String>,
+            #[bencode(rename = "md5sum")]
+            md5: Option<ByteString>,
+        }
+
+        let file = File { length: 3, path: vec!["a".to_string()], md5: None };
+        assert_eq!(b"d6:lengthi3e4:pathl1:aee".to_vec(), encode(file.to_bencode()));
+        assert_eq!(Ok(file), File::from_bencode(decode(b"d4:pathl1:ae6:lengthi3ee").unwrap()));
+        let file = File { length: 3, path: vec!["a".to_string()], md5: Some("x".to_byte_string()) };
+        assert_eq!(b"d6:lengthi3e6:md5sum1:x4:pathl1:aee".to_vec(), encode(file.to_bencode()));
+
+        assert_eq!(Ok(File { length: 1, path: vec![], md5: Some("x".to_byte_string()) }),
+                   File::from_bencode(decode(b"d6:lengthi1e6:md5sum1:x4:pathle5:extrai0ee").unwrap()));
+        assert_eq!(Err(ConvertError::MissingKey("length".to_byte_string())),
+                   File::from_bencode(decode(b"d4:pathlee").unwrap()));
+        assert_eq!(Err(ConvertError::UnexpectedType { expected: "dictionary", found: "list" }),
+                   File::from_bencode(decode(b"le").unwrap()));
+    }
+
+    #[test]
+    fn options_outside_dictionaries_are_lists() {
+        assert_eq!(b"le".to_vec(), encode(None::<i64>.to_bencode()));
+        assert_eq!(b"li5ee".to_vec(), encode(Some(5i64).to_bencode()));
+        assert_eq!(Ok(vec![None, Some(5i64)]), round_trip(&vec![None, Some(5i64)]));
+        assert_eq!(Err(ConvertError::InvalidLength { expected: 1, found: 2 }),
+                   Option::<i64>::from_bencode(decode(b"li1ei2ee").unwrap()));
+        assert_eq!(Err(ConvertError::UnexpectedType { expected: "list", found: "integer" }),
+                   Option::<i64>::from_bencode(decode(b"i1e").unwrap()));
+    }
+}
@@ -0,0 +1,319 @@
+// serde support for decoding: `from_bytes` deserializes any `Deserialize` type
+// straight from bencoded input.
+//
+// The input is decoded into a BEncodingRef first, so strings and byte strings
+// can be borrowed from it. Bencode has no booleans, floats or nulls: booleans
+// are read from the integers 0 and 1, a present value is always `Some` and
+// unit is the empty string.
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::{forward_to_deserialize_any, Deserialize};
+
+use crate::bdecode::{BDecoder, BEncodingRef};
+use crate::error::SerdeError;
+
+type Result<T> = std::result::Result<T, SerdeError>;
+
+impl de::Error for SerdeError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        SerdeError::Custom(msg.to_string())
+    }
+}
+
+// Deserializes a `T` from `bytes`, which must hold exactly one value
+pub fn from_bytes<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> Result<T> {
+    let mut decoder = BDecoder::new(bytes);
+    let value = decoder.decode_borrowed()?;
+    if decoder.cursor() != bytes.len() {
+        return Err(SerdeError::TrailingBytes(decoder.cursor()));
+    }
+    T::deserialize(value)
+}
+
+impl<'de> BEncodingRef<'de> {
+    fn unexpected(&self) -> de::Unexpected<'_> {
+        match self {
+            BEncodingRef::Integer(i) => de::Unexpected::Signed(*i),
+            BEncodingRef::String(s) => de::Unexpected::Bytes(s),
+            BEncodingRef::List(_) => de::Unexpected::Seq,
+            BEncodingRef::Dictionary(_) => de::Unexpected::Map,
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for BEncodingRef<'de> {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            BEncodingRef::Integer(i) => visitor.visit_i64(i),
+            BEncodingRef::String(s) => match std::str::from_utf8(s) {
+                Ok(s) => visitor.visit_borrowed_str(s),
+                Err(_) => visitor.visit_borrowed_bytes(s),
+            },
+            BEncodingRef::List(list) => visitor.visit_seq(SeqAccess(list.into_iter())),
+            BEncodingRef::Dictionary(dict) => visitor.visit_map(MapAccess { iter: dict.into_iter(), value: None }),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            BEncodingRef::Integer(0) => visitor.visit_bool(false),
+            BEncodingRef::Integer(1) => visitor.visit_bool(true),
+            other => Err(de::Error::invalid_type(other.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            BEncodingRef::String(s) => match std::str::from_utf8(s) {
+                Ok(s) => visitor.visit_borrowed_str(s),
+                Err(_) => Err(de::Error::invalid_value(de::Unexpected::Bytes(s), &visitor)),
+            },
+            other => Err(de::Error::invalid_type(other.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            BEncodingRef::String(s) => visitor.visit_borrowed_bytes(s),
+            other => Err(de::Error::invalid_type(other.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            BEncodingRef::String(b"") => visitor.visit_unit(),
+            other => Err(de::Error::invalid_type(other.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // Unit variants are plain strings, the others a dictionary with the
+    // variant name as its only key
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self {
+            BEncodingRef::String(_) => visitor.visit_enum(EnumAccess { variant: self, value: None }),
+            BEncodingRef::Dictionary(dict) if dict.len() == 1 => {
+                let (variant, value) = dict.into_iter().next().unwrap();
+                visitor.visit_enum(EnumAccess { variant: BEncodingRef::String(variant), value: Some(value) })
+            }
+            other => Err(de::Error::invalid_type(other.unexpected(), &"string or dictionary with a single key")),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64
+        seq tuple tuple_struct map struct identifier
+    }
+}
+
+impl<'de> IntoDeserializer<'de, SerdeError> for BEncodingRef<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+struct SeqAccess<'de>(std::vec::IntoIter<BEncodingRef<'de>>);
+
+impl<'de> de::SeqAccess<'de> for SeqAccess<'de> {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        self.0.next().map(|item| seed.deserialize(item)).transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct MapAccess<'de> {
+    iter: linked_hash_map::IntoIter<&'de [u8], BEncodingRef<'de>>,
+    value: Option<BEncodingRef<'de>>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess<'de> {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(BEncodingRef::String(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let value = self.value.take().ok_or_else(|| SerdeError::Custom("value requested before key".to_string()))?;
+        seed.deserialize(value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumAccess<'de> {
+    variant: BEncodingRef<'de>,
+    value: Option<BEncodingRef<'de>>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+    type Error = SerdeError;
+    type Variant = VariantAccess<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant)> {
+        let variant = seed.deserialize(self.variant)?;
+        Ok((variant, VariantAccess(self.value)))
+    }
+}
+
+struct VariantAccess<'de>(Option<BEncodingRef<'de>>);
+
+impl<'de> VariantAccess<'de> {
+    fn value(self, expected: &dyn de::Expected) -> Result<BEncodingRef<'de>> {
+        self.0.ok_or_else(|| de::Error::invalid_type(de::Unexpected::UnitVariant, expected))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess<'de> {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<()> {
+        match self.0 {
+            None => Ok(()),
+            Some(value) => Err(de::Error::invalid_type(value.unexpected(), &"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self.value(&"newtype variant")?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(self.value(&"tuple variant")?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_map(self.value(&"struct variant")?, visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use crate::bytestring::ToByteString;
+    use crate::error::DecodingError;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct File<'a> {
+        length: u64,
+        path: Vec<String>,
+        #[serde(borrow, default, with = "serde_bytes")]
+        md5: Option<&'a [u8]>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Info<'a> {
+        name: String,
+        #[serde(rename = "piece length")]
+        piece_length: u32,
+        #[serde(with = "serde_bytes")]
+        pieces: &'a [u8],
+        #[serde(borrow)]
+        files: Vec<File<'a>>,
+        private: Option<bool>,
+    }
+
+    #[test]
+    fn deserializes_structs() {
+        let inp = b"d5:filesld6:lengthi3e4:pathl1:a1:beed6:lengthi0e3:md52:\xff\x004:pathl1:ceee\
+                    4:name3:dir12:piece lengthi16384e6:pieces4:\x00\x01\x02\x037:privatei1e3:urli1ee";
+        let info: Info = from_bytes(inp).unwrap();
+        assert_eq!(Info {
+            name: "dir".to_string(),
+            piece_length: 16384,
+            pieces: b"\x00\x01\x02\x03",
+            files: vec![
+                File { length: 3, path: vec!["a".to_string(), "b".to_string()], md5: None },
+                File { length: 0, path: vec!["c".to_string()], md5: Some(b"\xff\x00") },
+            ],
+            private: Some(true),
+        }, info);
+        // Borrowed byte strings point into the input
+        assert_eq!(inp[113..].as_ptr(), info.pieces.as_ptr());
+    }
+
+    #[test]
+    fn deserializes_collections_and_enums() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        enum Msg {
+            Ping,
+            Value(i64),
+            Pair(String, String),
+            Error { code: u16 },
+        }
+
+        assert_eq!(Ok(vec![1, -2]), from_bytes::<Vec<i32>>(b"li1ei-2ee"));
+        assert_eq!(Ok((1u8, "x".to_string())), from_bytes(b"li1e1:xe"));
+        assert_eq!(Ok(HashMap::from([("a".to_string(), 1u8)])), from_bytes(b"d1:ai1ee"));
+        assert_eq!(Ok(()), from_bytes::<()>(b"0:"));
+        assert_eq!(Ok(vec![Msg::Ping, Msg::Value(3), Msg::Pair("a".to_string(), "b".to_string()), Msg::Error { code: 201 }]),
+                   from_bytes(b"l4:Pingd5:Valuei3eed4:Pairl1:a1:beed5:Errord4:codei201eeee"));
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(Err(SerdeError::Decoding(DecodingError::KeyWithoutValue("a".to_byte_string()))),
+                   from_bytes::<HashMap<String, i64>>(b"d1:ae"));
+        assert_eq!(Err(SerdeError::TrailingBytes(3)), from_bytes::<i64>(b"i1ei2e"));
+        assert_eq!(Err(SerdeError::Custom("invalid value: integer `300`, expected u8".to_string())),
+                   from_bytes::<u8>(b"i300e"));
+        assert_eq!(Err(SerdeError::Custom("missing field `pieces`".to_string())),
+                   from_bytes::<Info>(b"d5:filesle4:name1:a12:piece lengthi1ee"));
+        assert!(from_bytes::<String>(b"2:\xff\x00").is_err());
+        assert!(from_bytes::<bool>(b"i2e").is_err());
+    }
+}
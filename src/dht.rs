@@ -0,0 +1,157 @@
+// Kademlia node ids as used by the BitTorrent DHT (BEP 5). Ids live in a 160
+// bit space where the distance between two ids is their XOR, compared as a
+// big-endian integer, and the routing table keeps one bucket per bit of
+// shared prefix.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+
+use crate::bdecode::BEncodingType;
+use crate::convert::{FromBencode, ToBencode};
+use crate::error::ConvertError;
+
+pub const ID_LEN: usize = 20;
+
+// Number of buckets in a full routing table, one per bit of the id
+pub const BUCKETS: usize = ID_LEN * 8;
+
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct NodeId(pub [u8; ID_LEN]);
+
+impl NodeId {
+    // A uniformly random id. Uses the randomly seeded std hasher, which is
+    // fine for picking ids but not for anything security sensitive.
+    pub fn random() -> NodeId {
+        let mut id = [0; ID_LEN];
+        for chunk in id.chunks_mut(8) {
+            let random = RandomState::new().build_hasher().finish().to_be_bytes();
+            chunk.copy_from_slice(&random[..chunk.len()]);
+        }
+        NodeId(id)
+    }
+
+    pub fn distance(&self, other: &NodeId) -> NodeId {
+        let mut distance = [0; ID_LEN];
+        for (i, byte) in distance.iter_mut().enumerate() {
+            *byte = self.0[i] ^ other.0[i];
+        }
+        NodeId(distance)
+    }
+
+    // Number of leading bits shared with `other`
+    pub fn common_prefix(&self, other: &NodeId) -> usize {
+        let distance = self.distance(other);
+        match distance.0.iter().position(|&byte| byte != 0) {
+            Some(i) => i * 8 + distance.0[i].leading_zeros() as usize,
+            None => BUCKETS,
+        }
+    }
+
+    // Bucket `other` belongs to in the routing table of this node: the index
+    // of the highest differing bit, so 159 holds the farthest half of the
+    // space and 0 the closest pair. None for our own id.
+    pub fn bucket_index(&self, other: &NodeId) -> Option<usize> {
+        BUCKETS.checked_sub(self.common_prefix(other) + 1)
+    }
+
+    // A random id falling in `bucket` of this node, as used to refresh a
+    // bucket with a lookup. Returns None if `bucket` is not below BUCKETS.
+    pub fn random_in_bucket(&self, bucket: usize) -> Option<NodeId> {
+        if bucket >= BUCKETS {
+            return None;
+        }
+        let prefix = BUCKETS - 1 - bucket;
+        let mut id = NodeId::random().0;
+        for (i, byte) in id.iter_mut().enumerate() {
+            // Bits of this byte that are shared with self
+            let shared = prefix.saturating_sub(i * 8).min(8);
+            let mask = !(0xffu8.checked_shr(shared as u32).unwrap_or(0));
+            *byte = (self.0[i] & mask) | (*byte & !mask);
+        }
+        // Then the first bit that differs
+        let (byte, bit) = (prefix / 8, 0x80 >> (prefix % 8));
+        id[byte] = ((self.0[byte] & bit) ^ bit) | (id[byte] & !bit);
+        Some(NodeId(id))
+    }
+}
+
+impl fmt::Debug for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NodeId({})", self)
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+// An `id` field decodes straight into a NodeId, failing unless it holds
+// exactly 20 bytes
+impl ToBencode for NodeId {
+    fn to_bencode(&self) -> BEncodingType {
+        self.0.to_bencode()
+    }
+}
+
+impl FromBencode for NodeId {
+    fn from_bencode(value: BEncodingType) -> Result<Self, ConvertError> {
+        <[u8; ID_LEN]>::from_bencode(value).map(NodeId)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bdecode::decode;
+    use crate::bytestring::ToByteString;
+    use crate::convert::into_dict;
+
+    use super::*;
+
+    fn id(first: u8, last: u8) -> NodeId {
+        let mut id = [0; ID_LEN];
+        id[0] = first;
+        id[ID_LEN - 1] = last;
+        NodeId(id)
+    }
+
+    #[test]
+    fn xor_distance_and_buckets() {
+        let zero = NodeId([0; ID_LEN]);
+        assert_eq!(id(0x81, 0x01), id(0x80, 0x00).distance(&id(0x01, 0x01)));
+        assert!(zero.distance(&id(0, 2)) < zero.distance(&id(1, 0)));
+
+        assert_eq!(BUCKETS, zero.common_prefix(&zero));
+        assert_eq!(None, zero.bucket_index(&zero));
+        assert_eq!(Some(159), zero.bucket_index(&id(0x80, 0)));
+        assert_eq!(Some(152), zero.bucket_index(&id(0x01, 0)));
+        assert_eq!(Some(0), zero.bucket_index(&id(0, 0x01)));
+        assert_eq!(Some(1), id(0, 0x01).bucket_index(&id(0, 0x02)));
+    }
+
+    #[test]
+    fn random_ids_land_in_requested_bucket() {
+        let ours = NodeId::random();
+        assert_ne!(ours, NodeId::random());
+        for bucket in 0..BUCKETS {
+            let id = ours.random_in_bucket(bucket).unwrap();
+            assert_eq!(Some(bucket), ours.bucket_index(&id), "{} for {}", id, ours);
+        }
+        assert_eq!(None, ours.random_in_bucket(BUCKETS));
+    }
+
+    #[test]
+    fn decodes_id_fields() {
+        let mut args = into_dict(decode(b"d2:id20:abcdefghij01234567895:short3:abce").unwrap()).unwrap();
+        let node = NodeId::from_field(b"id", args.remove(b"id".as_slice())).unwrap();
+        assert_eq!("6162636465666768696a30313233343536373839", node.to_string());
+        assert_eq!(BEncodingType::String("abcdefghij0123456789".to_byte_string()), node.to_bencode());
+
+        assert_eq!(Err(ConvertError::InvalidValueForKey {
+            key: "short".to_byte_string(),
+            source: Box::new(ConvertError::InvalidLength { expected: ID_LEN, found: 3 }),
+        }), NodeId::from_field(b"short", args.remove(b"short".as_slice())));
+    }
+}
@@ -0,0 +1,121 @@
+// A convention for versioned application state: documents are stored as
+// `d7:payload...1:vi<version>ee` and older payloads are brought up to date by
+// a chain of migrations, each taking a payload from version n to n + 1.
+
+use std::collections::HashMap;
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::bdecode::BEncodingType;
+use crate::bytestring::ToByteString;
+use crate::convert::{into_dict, FromBencode};
+use crate::error::{ConvertError, EnvelopeError};
+
+type Result<T> = std::result::Result<T, EnvelopeError>;
+
+type Migration = Box<dyn Fn(BEncodingType) -> std::result::Result<BEncodingType, ConvertError>>;
+
+pub struct Envelope {
+    version: i64,
+    migrations: HashMap<i64, Migration>,
+}
+
+impl Envelope {
+    // Envelope for payloads at `version`, the version wrap writes
+    pub fn new(version: i64) -> Envelope {
+        Envelope { version, migrations: HashMap::new() }
+    }
+
+    // Registers the migration of payloads at `from` to `from + 1`
+    pub fn migration<F>(mut self, from: i64, migrate: F) -> Self
+        where F: Fn(BEncodingType) -> std::result::Result<BEncodingType, ConvertError> + 'static {
+        self.migrations.insert(from, Box::new(migrate));
+        self
+    }
+
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    pub fn wrap(&self, payload: BEncodingType) -> BEncodingType {
+        let mut dict = LinkedHashMap::new();
+        dict.insert("payload".to_byte_string(), payload);
+        dict.insert("v".to_byte_string(), BEncodingType::Integer(self.version));
+        BEncodingType::Dictionary(dict)
+    }
+
+    // Extracts the payload of `doc`, migrated to the current version
+    pub fn unwrap(&self, doc: BEncodingType) -> Result<BEncodingType> {
+        let mut dict = into_dict(doc).map_err(EnvelopeError::Malformed)?;
+        let mut version = i64::from_field(b"v", dict.remove(&"v".to_byte_string())).map_err(EnvelopeError::Malformed)?;
+        let mut payload = dict.remove(&"payload".to_byte_string())
+            .ok_or_else(|| EnvelopeError::Malformed(ConvertError::MissingKey("payload".to_byte_string())))?;
+        if version > self.version {
+            return Err(EnvelopeError::TooNew(version));
+        }
+        while version < self.version {
+            let migrate = self.migrations.get(&version).ok_or(EnvelopeError::MissingMigration(version))?;
+            payload = migrate(payload).map_err(|source| EnvelopeError::Migration { from: version, source })?;
+            version += 1;
+        }
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bdecode::decode;
+    use crate::bencode::encode_ref;
+    use crate::convert::ToBencode;
+
+    use super::*;
+
+    // v1 stored a bare name, v2 a dictionary with the name, v3 added a port
+    fn envelope() -> Envelope {
+        Envelope::new(3)
+            .migration(1, |name| {
+                let mut dict = LinkedHashMap::new();
+                dict.insert("name".to_byte_string(), name);
+                Ok(BEncodingType::Dictionary(dict))
+            })
+            .migration(2, |payload| {
+                let mut dict = into_dict(payload)?;
+                dict.insert("port".to_byte_string(), 6881i64.to_bencode());
+                Ok(BEncodingType::Dictionary(dict))
+            })
+    }
+
+    #[test]
+    fn wraps_current_version() {
+        let envelope = envelope();
+        let wrapped = envelope.wrap(BEncodingType::Integer(7));
+        assert_eq!(b"d7:payloadi7e1:vi3ee".to_vec(), encode_ref(&wrapped));
+        assert_eq!(Ok(BEncodingType::Integer(7)), envelope.unwrap(wrapped));
+    }
+
+    #[test]
+    fn migrates_old_payloads() {
+        let envelope = envelope();
+        let v1 = decode(b"d7:payload3:abc1:vi1ee").unwrap();
+        let v2 = decode(b"d7:payloadd4:name3:abce1:vi2ee").unwrap();
+        let expected = decode(b"d4:name3:abc4:porti6881ee").unwrap();
+        assert_eq!(Ok(expected.clone()), envelope.unwrap(v1));
+        assert_eq!(Ok(expected), envelope.unwrap(v2));
+    }
+
+    #[test]
+    fn reports_unusable_envelopes() {
+        let envelope = envelope();
+        let unwrap = |doc: &[u8]| envelope.unwrap(decode(doc).unwrap());
+
+        assert_eq!(Err(EnvelopeError::TooNew(4)), unwrap(b"d7:payloadi0e1:vi4ee"));
+        assert_eq!(Err(EnvelopeError::MissingMigration(0)), unwrap(b"d7:payloadi0e1:vi0ee"));
+        assert_eq!(Err(EnvelopeError::Migration {
+            from: 2,
+            source: ConvertError::UnexpectedType { expected: "dictionary", found: "integer" },
+        }), unwrap(b"d7:payloadi0e1:vi2ee"));
+        assert_eq!(Err(EnvelopeError::Malformed(ConvertError::MissingKey("payload".to_byte_string()))), unwrap(b"d1:vi3ee"));
+        assert!(matches!(unwrap(b"d7:payloadi0e1:v1:xe"), Err(EnvelopeError::Malformed(ConvertError::InvalidValueForKey { .. }))));
+        assert!(matches!(unwrap(b"li3ee"), Err(EnvelopeError::Malformed(ConvertError::UnexpectedType { .. }))));
+    }
+}
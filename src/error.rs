@@ -5,11 +5,21 @@ use crate::bytestring::ByteString;
 pub enum DecodingError {
     MissingIdentifier(char),
     KeyWithoutValue(ByteString),
+    InvalidValueForKey { key: ByteString, source: Box<DecodingError> },
     StringWithoutLength,
     NotANumber,
     EndOfFile,
+    UnterminatedList { start_offset: usize },
+    UnterminatedDictionary { start_offset: usize },
     NegativeZero,
     NegativeStringLen,
+    IntegerOverflow,
+    NestingTooDeep(usize),
+    SizeLimitExceeded(usize),
+    TimedOut,
+    Cancelled,
+    // Reading from an io::Read source failed
+    Io(std::io::ErrorKind),
 }
 
 impl fmt::Display for DecodingError {
@@ -17,11 +27,332 @@ impl fmt::Display for DecodingError {
         match self {
             DecodingError::MissingIdentifier(chr) => write!(f, "Expected identifier '{}'", chr),
             DecodingError::KeyWithoutValue(key) => write!(f, "Dictionary key '{}' without value", key),
+            DecodingError::InvalidValueForKey { key, source } =>
+                write!(f, "Invalid value for dictionary key '{}': {}", key, source),
             DecodingError::EndOfFile => write!(f, "Unexpected end of file"),
+            DecodingError::UnterminatedList { start_offset } =>
+                write!(f, "List starting at offset {} is never closed", start_offset),
+            DecodingError::UnterminatedDictionary { start_offset } =>
+                write!(f, "Dictionary starting at offset {} is never closed", start_offset),
             DecodingError::StringWithoutLength => write!(f, "Expected string length"),
             DecodingError::NotANumber => write!(f, "Expected a number but "),
             DecodingError::NegativeZero => write!(f, "Negative zero is not allowed. Use 0 instead"),
             DecodingError::NegativeStringLen => write!(f, "Negative string length is not allowed"),
+            DecodingError::IntegerOverflow => write!(f, "Number does not fit in a 64-bit signed integer"),
+            DecodingError::NestingTooDeep(limit) => write!(f, "Containers are nested deeper than {} levels", limit),
+            DecodingError::SizeLimitExceeded(limit) => write!(f, "Input is larger than the {} byte limit", limit),
+            DecodingError::TimedOut => write!(f, "Decoding took longer than allowed"),
+            DecodingError::Cancelled => write!(f, "Decoding was cancelled"),
+            DecodingError::Io(kind) => write!(f, "Reading input failed: {}", kind),
         }
     }
-}
\ No newline at end of file
+}
+
+impl std::error::Error for DecodingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodingError::InvalidValueForKey { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl DecodingError {
+    // Attributes an error in the value of `key` to that key. Budget and limit
+    // errors concern the input as a whole, so they are passed through as is and
+    // callers can match on them at any depth.
+    pub(crate) fn in_value_of(self, key: ByteString) -> DecodingError {
+        match self {
+            DecodingError::NestingTooDeep(_) | DecodingError::SizeLimitExceeded(_)
+            | DecodingError::TimedOut | DecodingError::Cancelled => self,
+            err => DecodingError::InvalidValueForKey { key, source: Box::new(err) },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum UrlError {
+    NotUtf8,
+    InvalidCharacter(char),
+    MissingScheme,
+    UnsupportedScheme(String),
+    MissingHost,
+    InvalidPort(String),
+    InvalidPercentEncoding(String),
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UrlError::NotUtf8 => write!(f, "Url is not valid UTF-8"),
+            UrlError::InvalidCharacter(chr) => write!(f, "Url contains invalid character {:?}", chr),
+            UrlError::MissingScheme => write!(f, "Url has no scheme"),
+            UrlError::UnsupportedScheme(scheme) => write!(f, "Unsupported url scheme '{}'", scheme),
+            UrlError::MissingHost => write!(f, "Url has no host"),
+            UrlError::InvalidPort(port) => write!(f, "Invalid port '{}'", port),
+            UrlError::InvalidPercentEncoding(seq) => write!(f, "Invalid percent-encoding '{}'", seq),
+        }
+    }
+}
+
+impl std::error::Error for UrlError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TrackerError {
+    Decoding(DecodingError),
+    // The tracker answered with a `failure reason`
+    Failure(ByteString),
+    NotADictionary,
+    MissingField(&'static str),
+    InvalidField(&'static str),
+}
+
+impl From<DecodingError> for TrackerError {
+    fn from(err: DecodingError) -> Self {
+        TrackerError::Decoding(err)
+    }
+}
+
+impl fmt::Display for TrackerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrackerError::Decoding(err) => write!(f, "Malformed tracker response: {}", err),
+            TrackerError::Failure(reason) => write!(f, "Tracker failure: {}", reason),
+            TrackerError::NotADictionary => write!(f, "Tracker response is not a dictionary"),
+            TrackerError::MissingField(key) => write!(f, "Tracker response is missing '{}'", key),
+            TrackerError::InvalidField(key) => write!(f, "Tracker response has an invalid '{}'", key),
+        }
+    }
+}
+
+impl std::error::Error for TrackerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TrackerError::Decoding(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "http-client")]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum HttpError {
+    Url(UrlError),
+    Io(std::io::ErrorKind),
+    // The tracker answered with a status other than 200
+    Status(u16),
+    // Not an HTTP response, or larger than the client accepts
+    MalformedResponse,
+    // The announce url does not follow the scrape convention
+    ScrapeUnsupported,
+    Tracker(TrackerError),
+}
+
+#[cfg(feature = "http-client")]
+impl From<UrlError> for HttpError {
+    fn from(err: UrlError) -> Self {
+        HttpError::Url(err)
+    }
+}
+
+#[cfg(feature = "http-client")]
+impl From<TrackerError> for HttpError {
+    fn from(err: TrackerError) -> Self {
+        HttpError::Tracker(err)
+    }
+}
+
+#[cfg(feature = "http-client")]
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HttpError::Url(err) => write!(f, "Invalid tracker url: {}", err),
+            HttpError::Io(kind) => write!(f, "Tracker request failed: {}", kind),
+            HttpError::Status(status) => write!(f, "Tracker answered with HTTP status {}", status),
+            HttpError::MalformedResponse => write!(f, "Tracker sent a malformed HTTP response"),
+            HttpError::ScrapeUnsupported => write!(f, "Tracker does not support scraping"),
+            HttpError::Tracker(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[cfg(feature = "http-client")]
+impl std::error::Error for HttpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HttpError::Url(err) => Some(err),
+            HttpError::Tracker(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// A value had a different type than the caller asked for
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TypeError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Expected {} but found {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+impl From<TypeError> for ConvertError {
+    fn from(err: TypeError) -> Self {
+        ConvertError::UnexpectedType { expected: err.expected, found: err.found }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConvertError {
+    UnexpectedType { expected: &'static str, found: &'static str },
+    MissingKey(ByteString),
+    InvalidValueForKey { key: ByteString, source: Box<ConvertError> },
+    NotUtf8,
+    OutOfRange(i64),
+    // List or tuple with the wrong number of items
+    InvalidLength { expected: usize, found: usize },
+    Custom(String),
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConvertError::UnexpectedType { expected, found } => write!(f, "Expected {} but found {}", expected, found),
+            ConvertError::MissingKey(key) => write!(f, "Missing dictionary key '{}'", key),
+            ConvertError::InvalidValueForKey { key, source } =>
+                write!(f, "Invalid value for dictionary key '{}': {}", key, source),
+            ConvertError::NotUtf8 => write!(f, "String is not valid UTF-8"),
+            ConvertError::OutOfRange(i) => write!(f, "Integer {} is out of range", i),
+            ConvertError::InvalidLength { expected, found } =>
+                write!(f, "Expected {} items but found {}", expected, found),
+            ConvertError::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConvertError::InvalidValueForKey { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EnvelopeError {
+    // Not a dictionary with an integer `v` and a `payload`
+    Malformed(ConvertError),
+    // Written by a newer version of the application
+    TooNew(i64),
+    // No migration registered from this version
+    MissingMigration(i64),
+    Migration { from: i64, source: ConvertError },
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnvelopeError::Malformed(err) => write!(f, "Malformed envelope: {}", err),
+            EnvelopeError::TooNew(version) => write!(f, "Envelope version {} is newer than supported", version),
+            EnvelopeError::MissingMigration(version) => write!(f, "No migration from envelope version {}", version),
+            EnvelopeError::Migration { from, source } =>
+                write!(f, "Migrating from envelope version {} failed: {}", from, source),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EnvelopeError::Malformed(err) | EnvelopeError::Migration { source: err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SerdeError {
+    Decoding(DecodingError),
+    // Input continues after the first value, at the given offset
+    TrailingBytes(usize),
+    // A value with no bencode representation, such as a float
+    Unsupported(&'static str),
+    Io(std::io::ErrorKind),
+    Custom(String),
+}
+
+#[cfg(feature = "serde")]
+impl From<DecodingError> for SerdeError {
+    fn from(err: DecodingError) -> Self {
+        SerdeError::Decoding(err)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerdeError::Decoding(err) => write!(f, "{}", err),
+            SerdeError::TrailingBytes(offset) => write!(f, "Unexpected trailing bytes at offset {}", offset),
+            SerdeError::Unsupported(what) => write!(f, "Cannot encode {} as bencode", what),
+            SerdeError::Io(kind) => write!(f, "Writing failed: {}", kind),
+            SerdeError::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for SerdeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SerdeError::Decoding(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConfigError {
+    Io(std::io::ErrorKind),
+    Serde(SerdeError),
+    // The file decodes, but is not in canonical form (unsorted or duplicate
+    // keys, integers with leading zeros)
+    NotCanonical,
+}
+
+#[cfg(feature = "serde")]
+impl From<SerdeError> for ConfigError {
+    fn from(err: SerdeError) -> Self {
+        ConfigError::Serde(err)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(kind) => write!(f, "Config file could not be accessed: {}", kind),
+            ConfigError::Serde(err) => write!(f, "Invalid config file: {}", err),
+            ConfigError::NotCanonical => write!(f, "Config file is not canonically encoded"),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Serde(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,101 @@
+// Rendering of untrusted byte strings for terminals and logs. Torrent names,
+// tracker messages and the like can carry escape sequences, so everything that
+// is not plain printable text is escaped and long strings are cut short.
+
+use std::fmt::{self, Display, Write};
+
+// Default number of characters shown before a string is truncated
+pub const MAX_DISPLAY_CHARS: usize = 200;
+
+// Displays `bytes` as text with control characters, bidirectional overrides
+// and invalid UTF-8 escaped, truncated after `max_chars` characters.
+pub struct Sanitized<'a> {
+    bytes: &'a [u8],
+    max_chars: usize,
+}
+
+pub fn sanitize(bytes: &[u8], max_chars: usize) -> Sanitized<'_> {
+    Sanitized { bytes, max_chars }
+}
+
+impl Display for Sanitized<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rest = self.bytes;
+        let mut shown = 0;
+        while !rest.is_empty() {
+            let (valid, invalid) = match std::str::from_utf8(rest) {
+                Ok(valid) => (valid, 0),
+                Err(err) => {
+                    let valid = std::str::from_utf8(&rest[..err.valid_up_to()]).unwrap_or_default();
+                    (valid, err.error_len().unwrap_or(rest.len() - err.valid_up_to()))
+                }
+            };
+            for (i, chr) in valid.char_indices() {
+                if shown == self.max_chars {
+                    return truncated(f, rest.len() - i);
+                }
+                write_char(f, chr)?;
+                shown += 1;
+            }
+            let invalid_start = valid.len();
+            for (i, byte) in rest[invalid_start..invalid_start + invalid].iter().enumerate() {
+                if shown == self.max_chars {
+                    return truncated(f, rest.len() - invalid_start - i);
+                }
+                write!(f, "\\x{:02x}", byte)?;
+                shown += 1;
+            }
+            rest = &rest[invalid_start + invalid..];
+        }
+        Ok(())
+    }
+}
+
+fn write_char(f: &mut fmt::Formatter<'_>, chr: char) -> fmt::Result {
+    match chr {
+        '\\' => f.write_str("\\\\"),
+        '\n' => f.write_str("\\n"),
+        '\r' => f.write_str("\\r"),
+        '\t' => f.write_str("\\t"),
+        // Bidirectional overrides and isolates can make text render reversed
+        '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => write!(f, "{}", chr.escape_unicode()),
+        _ if chr.is_control() => write!(f, "{}", chr.escape_unicode()),
+        _ => f.write_char(chr),
+    }
+}
+
+fn truncated(f: &mut fmt::Formatter<'_>, remaining: usize) -> fmt::Result {
+    write!(f, "… ({} more bytes)", remaining)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn show(bytes: &[u8], max_chars: usize) -> String {
+        sanitize(bytes, max_chars).to_string()
+    }
+
+    #[test]
+    fn passes_plain_text_through() {
+        assert_eq!("ubuntu-24.04.iso", show(b"ubuntu-24.04.iso", MAX_DISPLAY_CHARS));
+        assert_eq!("Ærø 日本", show("Ærø 日本".as_bytes(), MAX_DISPLAY_CHARS));
+        assert_eq!("", show(b"", 0));
+    }
+
+    #[test]
+    fn escapes_control_sequences() {
+        assert_eq!("\\u{1b}[2J\\u{7}name\\n", show(b"\x1b[2J\x07name\n", MAX_DISPLAY_CHARS));
+        assert_eq!("a\\u{202e}gpj.exe", show("a\u{202e}gpj.exe".as_bytes(), MAX_DISPLAY_CHARS));
+        assert_eq!("\\\\x41", show(b"\\x41", MAX_DISPLAY_CHARS));
+        assert_eq!("a\\xff\\xfeb\\xe6", show(b"a\xff\xfeb\xe6", MAX_DISPLAY_CHARS));
+    }
+
+    #[test]
+    fn truncates_long_strings() {
+        assert_eq!("abc… (3 more bytes)", show(b"abcdef", 3));
+        assert_eq!("日… (6 more bytes)", show("日本語".as_bytes(), 1));
+        assert_eq!("a\\xff… (2 more bytes)", show(b"a\xff\xfeb", 2));
+        assert_eq!("abc", show(b"abc", 3));
+    }
+}
@@ -0,0 +1,250 @@
+// Blocking announce and scrape requests against HTTP trackers, built on
+// std::net alone so the feature adds no dependencies. Requests go out as
+// HTTP/1.0, which keeps the server from answering with chunked encoding, and
+// the body is read until the tracker closes the connection. HTTPS and UDP
+// trackers are rejected with UnsupportedScheme; use a full HTTP client with
+// tracker::parse_announce_response for those.
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::bytestring::ByteString;
+use crate::error::{HttpError, UrlError};
+use crate::tracker::{self, AnnounceResponse, ScrapeStats, TrackerUrl};
+
+type Result<T> = std::result::Result<T, HttpError>;
+
+// Largest response accepted, headers included
+pub const MAX_RESPONSE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AnnounceRequest {
+    pub info_hash: [u8; 20],
+    pub peer_id: [u8; 20],
+    pub port: u16,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub left: u64,
+    // `started`, `stopped` or `completed`; None for regular announces
+    pub event: Option<String>,
+    pub compact: bool,
+}
+
+impl AnnounceRequest {
+    pub fn new(info_hash: [u8; 20], peer_id: [u8; 20], port: u16) -> AnnounceRequest {
+        AnnounceRequest { info_hash, peer_id, port, uploaded: 0, downloaded: 0, left: 0, event: None, compact: true }
+    }
+
+    pub fn uploaded(mut self, uploaded: u64) -> Self {
+        self.uploaded = uploaded;
+        self
+    }
+
+    pub fn downloaded(mut self, downloaded: u64) -> Self {
+        self.downloaded = downloaded;
+        self
+    }
+
+    pub fn left(mut self, left: u64) -> Self {
+        self.left = left;
+        self
+    }
+
+    pub fn event(mut self, event: &str) -> Self {
+        self.event = Some(event.to_string());
+        self
+    }
+
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+}
+
+// The announce url with the request's parameters added to its query. Info
+// hash and peer id are percent-encoded byte by byte.
+pub fn announce_url(announce: &ByteString, req: &AnnounceRequest) -> Result<String> {
+    let mut params = vec![
+        ("info_hash", percent_encode(&req.info_hash)),
+        ("peer_id", percent_encode(&req.peer_id)),
+        ("port", req.port.to_string()),
+        ("uploaded", req.uploaded.to_string()),
+        ("downloaded", req.downloaded.to_string()),
+        ("left", req.left.to_string()),
+        ("compact", if req.compact { "1" } else { "0" }.to_string()),
+    ];
+    if let Some(event) = &req.event {
+        params.push(("event", percent_encode(event.as_bytes())));
+    }
+    with_query(announce, &params)
+}
+
+pub fn announce(announce: &ByteString, req: &AnnounceRequest, timeout: Duration) -> Result<AnnounceResponse> {
+    let body = get(&announce_url(announce, req)?, timeout)?;
+    Ok(tracker::parse_announce_response(&body)?)
+}
+
+// Scrapes the tracker behind `announce` for the given info hashes, at the url
+// tracker::scrape_url derives
+pub fn scrape(announce: &ByteString, info_hashes: &[[u8; 20]], timeout: Duration) -> Result<Vec<([u8; 20], ScrapeStats)>> {
+    let scrape = tracker::scrape_url(announce).ok_or(HttpError::ScrapeUnsupported)?;
+    let params: Vec<_> = info_hashes.iter().map(|info_hash| ("info_hash", percent_encode(info_hash))).collect();
+    let body = get(&with_query(&scrape, &params)?, timeout)?;
+    Ok(tracker::parse_scrape_response(&body)?)
+}
+
+fn with_query(url: &ByteString, params: &[(&str, String)]) -> Result<String> {
+    let url = std::str::from_utf8(&url.0).or(Err(UrlError::NotUtf8))?;
+    let url = url.split('#').next().unwrap_or(url);
+    let mut out = url.to_string();
+    for (i, (key, value)) in params.iter().enumerate() {
+        let sep = if i == 0 && !url.contains('?') { '?' } else { '&' };
+        out.push(sep);
+        out.push_str(key);
+        out.push('=');
+        out.push_str(value);
+    }
+    Ok(out)
+}
+
+// Keeps the RFC 3986 unreserved characters and escapes every other byte
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 3);
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+// Sends a GET request and returns the body of a 200 response
+fn get(url: &str, timeout: Duration) -> Result<Vec<u8>> {
+    let url = ByteString(url.as_bytes().to_vec());
+    let parsed = TrackerUrl::parse(&url)?;
+    if parsed.scheme != "http" {
+        return Err(HttpError::Url(UrlError::UnsupportedScheme(parsed.scheme)));
+    }
+    let port = parsed.port.unwrap_or(80);
+    let host = parsed.host.trim_start_matches('[').trim_end_matches(']');
+    let path = if parsed.rest.starts_with('/') { parsed.rest.to_string() } else { format!("/{}", parsed.rest) };
+
+    let io_err = |err: std::io::Error| HttpError::Io(err.kind());
+    let addr = (host, port).to_socket_addrs().map_err(io_err)?.next()
+        .ok_or(HttpError::Io(std::io::ErrorKind::NotFound))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout).map_err(io_err)?;
+    stream.set_read_timeout(Some(timeout)).map_err(io_err)?;
+    stream.set_write_timeout(Some(timeout)).map_err(io_err)?;
+
+    let host_header = match parsed.port {
+        Some(port) if port != 80 => format!("{}:{}", parsed.host, port),
+        _ => parsed.host.clone(),
+    };
+    write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: domenec/{}\r\nConnection: close\r\n\r\n",
+           path, host_header, env!("CARGO_PKG_VERSION")).map_err(io_err)?;
+
+    let mut response = Vec::new();
+    stream.take(MAX_RESPONSE as u64 + 1).read_to_end(&mut response).map_err(io_err)?;
+    if response.len() > MAX_RESPONSE {
+        return Err(HttpError::MalformedResponse);
+    }
+    parse_response(response)
+}
+
+fn parse_response(mut response: Vec<u8>) -> Result<Vec<u8>> {
+    let header_end = response.windows(4).position(|window| window == b"\r\n\r\n")
+        .ok_or(HttpError::MalformedResponse)?;
+    let status_line = response[..header_end].split(|&b| b == b'\r').next().unwrap_or(&[]);
+    let status = std::str::from_utf8(status_line).ok()
+        .filter(|line| line.starts_with("HTTP/"))
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or(HttpError::MalformedResponse)?;
+    if status != 200 {
+        return Err(HttpError::Status(status));
+    }
+    Ok(response.split_off(header_end + 4))
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use crate::bytestring::ToByteString;
+    use crate::error::TrackerError;
+    use crate::tracker::{AnnounceResponseBuilder, ScrapeResponseBuilder};
+
+    use super::*;
+
+    // Answers one request with `response` and hands back the request line
+    fn serve_once(response: Vec<u8>) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream.write_all(&response).unwrap();
+            String::from_utf8(request).unwrap().lines().next().unwrap().to_string()
+        });
+        (base, handle)
+    }
+
+    fn ok(body: Vec<u8>) -> Vec<u8> {
+        let mut response = format!("HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+        response.extend(body);
+        response
+    }
+
+    #[test]
+    fn builds_announce_urls() {
+        let req = AnnounceRequest::new([0xab; 20], *b"-DM0001-a b/~._0123x", 6881).left(10).event("started");
+        assert_eq!(Ok(format!("http://t.org/announce?passkey=x&info_hash={}&peer_id=-DM0001-a%20b%2F~._0123x\
+&port=6881&uploaded=0&downloaded=0&left=10&compact=1&event=started", "%AB".repeat(20))),
+                   announce_url(&"http://t.org/announce?passkey=x#frag".to_byte_string(), &req));
+    }
+
+    #[test]
+    fn announces_and_scrapes() {
+        let body = AnnounceResponseBuilder::new(1800).peers(vec!["10.0.0.1:6881".parse().unwrap()]).build();
+        let (base, server) = serve_once(ok(body.clone()));
+        let req = AnnounceRequest::new([1; 20], [2; 20], 6881);
+        let resp = announce(&format!("{}/announce", base).as_str().to_byte_string(), &req, Duration::from_secs(5));
+        assert_eq!(tracker::parse_announce_response(&body).map_err(HttpError::Tracker), resp);
+        assert!(server.join().unwrap().starts_with("GET /announce?info_hash=%01%01"));
+
+        let stats = ScrapeStats { complete: 1, downloaded: 2, incomplete: 3 };
+        let (base, server) = serve_once(ok(ScrapeResponseBuilder::new().file([1; 20], stats).build()));
+        let resp = scrape(&format!("{}/announce", base).as_str().to_byte_string(), &[[1; 20]], Duration::from_secs(5));
+        assert_eq!(Ok(vec![([1; 20], stats)]), resp);
+        assert!(server.join().unwrap().starts_with(&format!("GET /scrape?info_hash={} HTTP/1.0", "%01".repeat(20))));
+    }
+
+    #[test]
+    fn reports_http_and_tracker_errors() {
+        let (base, server) = serve_once(b"HTTP/1.1 404 Not Found\r\n\r\n".to_vec());
+        let req = AnnounceRequest::new([1; 20], [2; 20], 6881);
+        assert_eq!(Err(HttpError::Status(404)),
+                   announce(&format!("{}/announce", base).as_str().to_byte_string(), &req, Duration::from_secs(5)));
+        server.join().unwrap();
+
+        let (base, server) = serve_once(ok(tracker::failure_response("banned")));
+        assert_eq!(Err(HttpError::Tracker(TrackerError::Failure("banned".to_byte_string()))),
+                   announce(&format!("{}/announce", base).as_str().to_byte_string(), &req, Duration::from_secs(5)));
+        server.join().unwrap();
+
+        assert_eq!(Err(HttpError::MalformedResponse), parse_response(b"garbage".to_vec()));
+        assert_eq!(Err(HttpError::ScrapeUnsupported),
+                   scrape(&"http://t.org/a".to_byte_string(), &[[1; 20]], Duration::from_secs(5)));
+        assert_eq!(Err(HttpError::Url(UrlError::UnsupportedScheme("https".to_string()))),
+                   announce(&"https://t.org/announce".to_byte_string(), &req, Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,320 @@
+use std::cell::OnceCell;
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::bdecode::{decode, BDecoder, BEncodingType};
+use crate::error::DecodingError;
+
+type Result<T> = std::result::Result<T, DecodingError>;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Kind {
+    Integer,
+    String,
+    List,
+    Dictionary,
+}
+
+// A single value in a StructureIndex. Dictionary entries are stored as a key
+// node (always a String) directly followed by the nodes of its value.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Node {
+    pub kind: Kind,
+    // Span of the encoded value, including its prefix and terminator
+    pub start: usize,
+    pub end: usize,
+    // Position of the first node after this value's subtree
+    pub next: usize,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PathSegment<'p> {
+    Key(&'p [u8]),
+    Index(usize),
+}
+
+impl<'p> From<&'p str> for PathSegment<'p> {
+    fn from(key: &'p str) -> Self {
+        PathSegment::Key(key.as_bytes())
+    }
+}
+
+impl<'p> From<&'p [u8]> for PathSegment<'p> {
+    fn from(key: &'p [u8]) -> Self {
+        PathSegment::Key(key)
+    }
+}
+
+impl From<usize> for PathSegment<'_> {
+    fn from(index: usize) -> Self {
+        PathSegment::Index(index)
+    }
+}
+
+// Offsets and kinds of every value in a document, built in one pass without
+// allocating any values. Subtrees can then be decoded on demand, which gives
+// cheap random access into large documents.
+#[derive(Debug, Clone)]
+pub struct StructureIndex<'a> {
+    bytes: &'a [u8],
+    nodes: Vec<Node>,
+}
+
+impl<'a> StructureIndex<'a> {
+    // Indexes the value at the start of `bytes`. The input is validated with
+    // the same rules as `decode`.
+    pub fn build(bytes: &'a [u8]) -> Result<StructureIndex<'a>> {
+        let mut nodes = Vec::new();
+        index_value(&mut BDecoder::new(bytes), &mut nodes)?;
+        Ok(StructureIndex { bytes, nodes })
+    }
+
+    // All nodes in document order. The root is always at position 0.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    // Direct children of a container. For dictionaries keys and values alternate.
+    pub fn children(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        let end = self.nodes.get(node).map_or(node, |n| n.next);
+        let mut child = node + 1;
+        std::iter::from_fn(move || {
+            if child >= end {
+                return None;
+            }
+            let current = child;
+            child = self.nodes[current].next;
+            Some(current)
+        })
+    }
+
+    // Looks up `key` in the dictionary at `node` and returns its value node
+    pub fn get(&self, node: usize, key: &[u8]) -> Option<usize> {
+        if self.nodes.get(node)?.kind != Kind::Dictionary {
+            return None;
+        }
+        let mut children = self.children(node);
+        while let (Some(k), Some(v)) = (children.next(), children.next()) {
+            if self.str_content(k) == Some(key) {
+                return Some(v);
+            }
+        }
+        None
+    }
+
+    pub fn find(&self, path: &[PathSegment]) -> Option<usize> {
+        path.iter().try_fold(0, |node, segment| match segment {
+            PathSegment::Key(key) => self.get(node, key),
+            PathSegment::Index(i) if self.nodes.get(node)?.kind == Kind::List => self.children(node).nth(*i),
+            PathSegment::Index(_) => None,
+        })
+    }
+
+    // Encoded bytes of the value at `node`
+    pub fn raw(&self, node: usize) -> Option<&'a [u8]> {
+        self.nodes.get(node).map(|n| &self.bytes[n.start..n.end])
+    }
+
+    // Content of a string node, without its length prefix
+    pub fn str_content(&self, node: usize) -> Option<&'a [u8]> {
+        let raw = self.raw(node).filter(|_| self.nodes[node].kind == Kind::String)?;
+        let colon = raw.iter().position(|&b| b == b':')?;
+        Some(&raw[colon + 1..])
+    }
+
+    pub fn decode_node(&self, node: usize) -> Option<BEncodingType> {
+        // The span was already validated while indexing, so this can't fail
+        decode(self.raw(node)?).ok()
+    }
+
+    // Decodes only the subtree found at `path`
+    pub fn decode_at(&self, path: &[PathSegment]) -> Option<BEncodingType> {
+        self.decode_node(self.find(path)?)
+    }
+}
+
+// Top-level dictionary whose values are only decoded, and then cached, when
+// they are first accessed. Reading two keys of a large document skips the
+// cost of decoding the rest.
+#[derive(Debug)]
+pub struct LazyDict<'a> {
+    index: StructureIndex<'a>,
+    entries: LinkedHashMap<&'a [u8], (usize, OnceCell<BEncodingType>)>,
+}
+
+impl<'a> LazyDict<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<LazyDict<'a>> {
+        LazyDict::from_index(StructureIndex::build(bytes)?)
+    }
+
+    pub fn from_index(index: StructureIndex<'a>) -> Result<LazyDict<'a>> {
+        if index.nodes[0].kind != Kind::Dictionary {
+            return Err(DecodingError::MissingIdentifier('d'));
+        }
+        let mut entries = LinkedHashMap::new();
+        {
+            let mut children = index.children(0);
+            while let (Some(k), Some(v)) = (children.next(), children.next()) {
+                if let Some(key) = index.str_content(k) {
+                    entries.insert(key, (v, OnceCell::new()));
+                }
+            }
+        }
+        Ok(LazyDict { index, entries })
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&BEncodingType> {
+        let (node, cell) = self.entries.get(key)?;
+        if let Some(value) = cell.get() {
+            return Some(value);
+        }
+        let value = self.index.decode_node(*node)?;
+        Some(cell.get_or_init(|| value))
+    }
+
+    // Encoded bytes of the value under `key`, without decoding it
+    pub fn raw(&self, key: &[u8]) -> Option<&'a [u8]> {
+        self.entries.get(key).and_then(|(node, _)| self.index.raw(*node))
+    }
+
+    pub fn is_decoded(&self, key: &[u8]) -> bool {
+        self.entries.get(key).is_some_and(|(_, cell)| cell.get().is_some())
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &'a [u8]> + '_ {
+        self.entries.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn index(&self) -> &StructureIndex<'a> {
+        &self.index
+    }
+}
+
+fn index_value(decoder: &mut BDecoder, nodes: &mut Vec<Node>) -> Result<()> {
+    decoder.check_budget()?;
+    let pos = nodes.len();
+    let start = decoder.cursor();
+    nodes.push(Node { kind: Kind::Integer, start, end: start, next: pos + 1 });
+    let kind = match decoder.peek()? {
+        b'i' => {
+            decoder.parse_int()?;
+            Kind::Integer
+        }
+        b'l' => {
+            decoder.nested(|decoder| decoder.walk_list(|decoder| index_value(decoder, nodes)))?;
+            Kind::List
+        }
+        b'd' => {
+            decoder.nested(|decoder| decoder.walk_dict(|decoder, key| {
+                let next = nodes.len() + 1;
+                nodes.push(Node { kind: Kind::String, start: key.start, end: key.end, next });
+                index_value(decoder, nodes)
+            }))?;
+            Kind::Dictionary
+        }
+        _ => {
+            decoder.read_str_span()?;
+            Kind::String
+        }
+    };
+    nodes[pos] = Node { kind, start, end: decoder.cursor(), next: nodes.len() };
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bytestring::ToByteString;
+
+    use super::*;
+
+    const TORRENT: &[u8] = b"d8:announce3:url4:infod6:lengthi42e4:name1:a6:pieces4:xxxxe5:filesli1ei2ei3eee";
+
+    #[test]
+    fn indexes_all_values() {
+        let index = StructureIndex::build(b"d1:ali1e2:bce1:bi-5ee").unwrap();
+        assert_eq!(&[
+            Node { kind: Kind::Dictionary, start: 0, end: 21, next: 7 },
+            Node { kind: Kind::String, start: 1, end: 4, next: 2 },
+            Node { kind: Kind::List, start: 4, end: 13, next: 5 },
+            Node { kind: Kind::Integer, start: 5, end: 8, next: 4 },
+            Node { kind: Kind::String, start: 8, end: 12, next: 5 },
+            Node { kind: Kind::String, start: 13, end: 16, next: 6 },
+            Node { kind: Kind::Integer, start: 16, end: 20, next: 7 },
+        ], index.nodes());
+        assert_eq!(vec![1, 2, 5, 6], index.children(0).collect::<Vec<_>>());
+        assert_eq!(vec![3, 4], index.children(2).collect::<Vec<_>>());
+        assert_eq!(0, index.children(3).count());
+        assert_eq!(0, index.children(100).count());
+    }
+
+    #[test]
+    fn finds_paths() {
+        let index = StructureIndex::build(TORRENT).unwrap();
+
+        assert_eq!(Some(b"3:url".as_ref()), index.find(&["announce".into()]).and_then(|n| index.raw(n)));
+        assert_eq!(Some(b"4:xxxx".as_ref()), index.find(&["info".into(), "pieces".into()]).and_then(|n| index.raw(n)));
+        assert_eq!(Some(b"i3e".as_ref()), index.find(&["files".into(), 2.into()]).and_then(|n| index.raw(n)));
+        assert_eq!(None, index.find(&["files".into(), 3.into()]));
+        assert_eq!(None, index.find(&["info".into(), 0.into()]));
+        assert_eq!(None, index.find(&["announce".into(), "x".into()]));
+        assert_eq!(None, index.find(&["missing".into()]));
+        assert_eq!(Some(0), index.find(&[]));
+    }
+
+    #[test]
+    fn decodes_subtrees_on_demand() {
+        let index = StructureIndex::build(TORRENT).unwrap();
+
+        assert_eq!(Some(BEncodingType::Integer(42)), index.decode_at(&["info".into(), "length".into()]));
+        assert_eq!(Some(BEncodingType::String("a".to_byte_string())), index.decode_at(&["info".into(), "name".into()]));
+        assert_eq!(Some(BEncodingType::List(vec![BEncodingType::Integer(1), BEncodingType::Integer(2), BEncodingType::Integer(3)])),
+                   index.decode_at(&["files".into()]));
+        assert_eq!(decode(TORRENT).ok(), index.decode_at(&[]));
+        assert_eq!(Some(b"url".as_ref()), index.find(&["announce".into()]).and_then(|n| index.str_content(n)));
+    }
+
+    #[test]
+    fn reports_decoding_errors() {
+        assert_eq!(Err(DecodingError::UnterminatedList { start_offset: 0 }), StructureIndex::build(b"li1e").map(|_| ()));
+        assert_eq!(Err(DecodingError::KeyWithoutValue("a".to_byte_string())), StructureIndex::build(b"d1:ae").map(|_| ()));
+        assert_eq!(Err(DecodingError::EndOfFile), StructureIndex::build(b"").map(|_| ()));
+    }
+
+    #[test]
+    fn lazy_dict_decodes_on_access() {
+        let dict = LazyDict::new(TORRENT).unwrap();
+
+        assert_eq!(3, dict.len());
+        assert_eq!(vec![b"announce".as_ref(), b"info", b"files"], dict.keys().collect::<Vec<_>>());
+        assert!(!dict.is_decoded(b"announce"));
+        assert!(!dict.is_decoded(b"info"));
+
+        assert_eq!(Some(&BEncodingType::String("url".to_byte_string())), dict.get(b"announce"));
+        assert!(dict.is_decoded(b"announce"));
+        assert!(!dict.is_decoded(b"info"));
+        assert!(std::ptr::eq(dict.get(b"announce").unwrap(), dict.get(b"announce").unwrap()));
+
+        assert_eq!(Some(b"d6:lengthi42e4:name1:a6:pieces4:xxxxe".as_ref()), dict.raw(b"info"));
+        assert!(!dict.is_decoded(b"info"));
+        assert_eq!(None, dict.get(b"missing"));
+        assert!(!dict.contains_key(b"missing"));
+    }
+
+    #[test]
+    fn lazy_dict_requires_dictionary() {
+        assert_eq!(Err(DecodingError::MissingIdentifier('d')), LazyDict::new(b"li1ee").map(|_| ()));
+        assert!(LazyDict::new(b"de").unwrap().is_empty());
+    }
+}
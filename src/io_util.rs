@@ -0,0 +1,153 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Tells apart the temporary files of concurrent writes within this process
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Replaces the file at `path` with `contents` without ever exposing a half
+// written file. The data goes to a temporary sibling first which is then
+// renamed over the target. If `backup_ext` is given and the target exists, the
+// previous contents are kept next to it as `<file name>.<backup_ext>`.
+//
+// Concurrent writers each use their own temporary file, so the last rename
+// wins and no writer sees another's partial data.
+pub fn write_atomic(path: &Path, contents: &[u8], backup_ext: Option<&str>) -> io::Result<()> {
+    let unique = format!(".{}.{}.tmp", std::process::id(), TMP_COUNTER.fetch_add(1, Ordering::Relaxed));
+    let tmp = sibling_path(path, ".", &unique)?;
+    if let Err(err) = write_synced(&tmp, contents) {
+        let _ = fs::remove_file(&tmp);
+        return Err(err);
+    }
+
+    if let Some(ext) = backup_ext {
+        if path.exists() {
+            if let Err(err) = fs::copy(path, sibling_path(path, "", &format!(".{}", ext))?) {
+                let _ = fs::remove_file(&tmp);
+                return Err(err);
+            }
+        }
+    }
+
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })?;
+    sync_parent(path)
+}
+
+// Makes the rename itself durable. Without this a crash can bring back the
+// old directory entry even though the new contents were synced.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+// Directories cannot be opened as files elsewhere, and the rename is as
+// durable as the platform makes it
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    // A leftover file with the same name is never reused
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+// Builds `<dir>/<prefix><file name><suffix>` for the given path
+fn sibling_path(path: &Path, prefix: &str, suffix: &str) -> io::Result<PathBuf> {
+    let file_name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut name = OsString::from(prefix);
+    name.push(file_name);
+    name.push(suffix);
+    Ok(path.with_file_name(name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("domenec-io-util-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn writes_new_file() {
+        let dir = scratch_dir("new");
+        let path = dir.join("a.torrent");
+
+        write_atomic(&path, b"de", None).unwrap();
+        assert_eq!(b"de".to_vec(), fs::read(&path).unwrap());
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn replaces_and_backs_up() {
+        let dir = scratch_dir("backup");
+        let path = dir.join("a.torrent");
+        fs::write(&path, b"le").unwrap();
+
+        write_atomic(&path, b"de", Some("bak")).unwrap();
+        assert_eq!(b"de".to_vec(), fs::read(&path).unwrap());
+        assert_eq!(b"le".to_vec(), fs::read(dir.join("a.torrent.bak")).unwrap());
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn backup_skipped_for_missing_target() {
+        let dir = scratch_dir("nobackup");
+        let path = dir.join("a.torrent");
+
+        write_atomic(&path, b"de", Some("bak")).unwrap();
+        assert!(!dir.join("a.torrent.bak").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_writers_do_not_collide() {
+        let dir = scratch_dir("concurrent");
+        let path = dir.join("a.torrent");
+
+        let writers: Vec<_> = (0..8).map(|i| {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                for _ in 0..20 {
+                    write_atomic(&path, format!("i{}e", i).as_bytes(), None).unwrap();
+                }
+            })
+        }).collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let contents = fs::read(&path).unwrap();
+        assert!(contents.len() == 3 && contents.starts_with(b"i") && contents.ends_with(b"e"));
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write_leaves_target_untouched() {
+        let dir = scratch_dir("fail");
+        let path = dir.join("missing").join("a.torrent");
+
+        assert!(write_atomic(&path, b"de", None).is_err());
+        assert!(!path.exists());
+        assert!(write_atomic(Path::new("/"), b"de", None).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
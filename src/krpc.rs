@@ -0,0 +1,143 @@
+// Pieces of the DHT's KRPC protocol (BEP 5, BEP 32). Nodes are exchanged in
+// `nodes` and `nodes6` strings, each entry being a 20 byte node id followed
+// by the node's compact address.
+
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use crate::bytestring::ByteString;
+use crate::compact::{self, PEER_V4_LEN, PEER_V6_LEN};
+use crate::dht::{NodeId, ID_LEN};
+
+pub const NODE_V4_LEN: usize = ID_LEN + PEER_V4_LEN;
+pub const NODE_V6_LEN: usize = ID_LEN + PEER_V6_LEN;
+
+// Packed node lists for a response. Either may be empty, in which case the
+// field is usually left out.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CompactNodes {
+    pub nodes: ByteString,
+    pub nodes6: ByteString,
+}
+
+// Packs nodes taken from a routing table, IPv4 ones into `nodes` and IPv6
+// ones into `nodes6`
+#[derive(Debug, Clone, Default)]
+pub struct NodesBuilder {
+    nodes: Vec<u8>,
+    nodes6: Vec<u8>,
+}
+
+impl NodesBuilder {
+    pub fn new() -> NodesBuilder {
+        NodesBuilder::default()
+    }
+
+    pub fn node(mut self, id: NodeId, addr: SocketAddr) -> Self {
+        self.push(id, addr);
+        self
+    }
+
+    fn push(&mut self, id: NodeId, addr: SocketAddr) {
+        match addr {
+            SocketAddr::V4(addr) => {
+                self.nodes.extend(id.0);
+                compact::write_peer_v4(&mut self.nodes, &addr);
+            }
+            SocketAddr::V6(addr) => {
+                self.nodes6.extend(id.0);
+                compact::write_peer_v6(&mut self.nodes6, &addr);
+            }
+        }
+    }
+
+    pub fn build(self) -> CompactNodes {
+        CompactNodes { nodes: ByteString(self.nodes), nodes6: ByteString(self.nodes6) }
+    }
+}
+
+impl Extend<(NodeId, SocketAddr)> for NodesBuilder {
+    fn extend<I: IntoIterator<Item = (NodeId, SocketAddr)>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|(id, addr)| self.push(id, addr));
+    }
+}
+
+impl FromIterator<(NodeId, SocketAddr)> for NodesBuilder {
+    fn from_iter<I: IntoIterator<Item = (NodeId, SocketAddr)>>(iter: I) -> Self {
+        let mut builder = NodesBuilder::new();
+        builder.extend(iter);
+        builder
+    }
+}
+
+// Entries of a `nodes` string. Returns None if its length is not a multiple
+// of NODE_V4_LEN.
+pub fn nodes(bytes: &[u8]) -> Option<impl Iterator<Item = (NodeId, SocketAddrV4)> + '_> {
+    if bytes.len() % NODE_V4_LEN != 0 {
+        return None;
+    }
+    Some(bytes.chunks_exact(NODE_V4_LEN).map(|chunk| {
+        let (id, addr) = chunk.split_at(ID_LEN);
+        (NodeId(id.try_into().expect("exact chunk")), compact::read_peer_v4(addr.try_into().expect("exact chunk")))
+    }))
+}
+
+// Like nodes, for `nodes6` strings
+pub fn nodes6(bytes: &[u8]) -> Option<impl Iterator<Item = (NodeId, SocketAddrV6)> + '_> {
+    if bytes.len() % NODE_V6_LEN != 0 {
+        return None;
+    }
+    Some(bytes.chunks_exact(NODE_V6_LEN).map(|chunk| {
+        let (id, addr) = chunk.split_at(ID_LEN);
+        (NodeId(id.try_into().expect("exact chunk")), compact::read_peer_v6(addr.try_into().expect("exact chunk")))
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn id(byte: u8) -> NodeId {
+        NodeId([byte; ID_LEN])
+    }
+
+    #[test]
+    fn packs_nodes_by_family() {
+        let packed = NodesBuilder::new()
+            .node(id(1), "1.2.3.4:258".parse().unwrap())
+            .node(id(2), "[::1]:80".parse().unwrap())
+            .node(id(3), "5.6.7.8:1".parse().unwrap())
+            .build();
+
+        let mut expected = vec![1; ID_LEN];
+        expected.extend([1, 2, 3, 4, 1, 2]);
+        expected.extend([3; ID_LEN]);
+        expected.extend([5, 6, 7, 8, 0, 1]);
+        assert_eq!(expected, packed.nodes.0);
+        assert_eq!(2 * NODE_V4_LEN, packed.nodes.0.len());
+
+        let mut expected6 = vec![2; ID_LEN];
+        expected6.extend(std::net::Ipv6Addr::LOCALHOST.octets());
+        expected6.extend([0, 80]);
+        assert_eq!(expected6, packed.nodes6.0);
+    }
+
+    #[test]
+    fn parses_packed_nodes() {
+        let table: Vec<(NodeId, SocketAddr)> = vec![
+            (id(7), "10.0.0.1:6881".parse().unwrap()),
+            (id(8), "[2001:db8::2]:6882".parse().unwrap()),
+            (id(9), "10.0.0.3:6883".parse().unwrap()),
+        ];
+        let packed = table.iter().copied().collect::<NodesBuilder>().build();
+
+        let v4: Vec<_> = nodes(&packed.nodes.0).unwrap().map(|(id, addr)| (id, SocketAddr::V4(addr))).collect();
+        let v6: Vec<_> = nodes6(&packed.nodes6.0).unwrap().map(|(id, addr)| (id, SocketAddr::V6(addr))).collect();
+        assert_eq!(vec![table[0], table[2]], v4);
+        assert_eq!(vec![table[1]], v6);
+
+        assert_eq!(0, nodes(b"").unwrap().count());
+        assert!(nodes(&packed.nodes.0[1..]).is_none());
+        assert!(nodes(&packed.nodes6.0).is_none());
+        assert!(nodes6(&packed.nodes.0).is_none());
+    }
+}
@@ -0,0 +1,63 @@
+// Unsafe code is only allowed when explicitly opted into.
+#![cfg_attr(not(feature = "unsafe-opt"), forbid(unsafe_code))]
+
+// Lets derived impls refer to `::domenec` paths from inside this crate too
+#[cfg(feature = "derive")]
+extern crate self as domenec;
+
+pub mod access;
+pub mod analytics;
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod bdecode;
+pub mod bencode;
+pub mod bounded;
+pub mod build_info;
+pub mod builder;
+pub mod bytestring;
+pub mod compact;
+#[cfg(feature = "serde")]
+pub mod config;
+pub mod convert;
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod dht;
+pub mod envelope;
+pub mod error;
+pub mod fmt;
+#[cfg(feature = "http-client")]
+pub mod http_client;
+pub mod index;
+pub mod io_util;
+pub mod krpc;
+pub mod literal;
+#[cfg(feature = "hashing")]
+pub mod merkle;
+pub mod peerid;
+pub mod push;
+pub mod redact;
+#[cfg(feature = "serde")]
+pub mod ser;
+pub mod sniff;
+pub mod tape;
+#[cfg(test)]
+mod test_util;
+pub mod token;
+pub mod tracker;
+pub mod visit;
+
+pub use bdecode::{decode, decode_borrowed, decode_from_reader, read_bytes_at, read_int_at, BEncodingRef, BEncodingType};
+#[cfg(feature = "async")]
+pub use async_io::{decode_async, decode_async_with_limit};
+pub use bencode::{encode, encode_ref, encode_to_writer};
+pub use build_info::{build_info, BuildInfo, MSRV};
+pub use bytestring::ByteString;
+pub use convert::{FromBencode, ToBencode};
+#[cfg(feature = "derive")]
+pub use domenec_derive::{FromBencode, ToBencode};
+pub use error::DecodingError;
+#[cfg(feature = "serde")]
+pub use de::from_bytes;
+#[cfg(feature = "serde")]
+pub use ser::{to_bytes, to_writer};
+pub use visit::{decode_with_visitor, BVisitor};
@@ -0,0 +1,252 @@
+// Compile time validation for bencoded constants such as protocol message templates.
+//
+// Everything here is a `const fn`, so it can't allocate or recurse through the
+// regular decoder. Containers are tracked on a fixed-size stack instead.
+
+const MAX_DEPTH: usize = 64;
+
+const LIST: u8 = 0;
+const DICT_KEY: u8 = 1;
+const DICT_VALUE: u8 = 2;
+
+// Checks that `bytes` holds exactly one well-formed bencoded value
+pub const fn is_valid(bytes: &[u8]) -> bool {
+    let mut stack = [LIST; MAX_DEPTH];
+    let mut depth = 0;
+    let mut i = 0;
+    loop {
+        if i >= bytes.len() {
+            return false;
+        }
+        let chr = bytes[i];
+        if depth > 0 && chr == b'e' {
+            if stack[depth - 1] == DICT_VALUE {
+                return false;
+            }
+            depth -= 1;
+            i += 1;
+        } else if depth > 0 && stack[depth - 1] == DICT_KEY {
+            match read_str(bytes, i) {
+                Some(end) => i = end,
+                None => return false,
+            }
+            stack[depth - 1] = DICT_VALUE;
+            continue;
+        } else if chr == b'l' || chr == b'd' {
+            if depth == MAX_DEPTH {
+                return false;
+            }
+            stack[depth] = if chr == b'l' { LIST } else { DICT_KEY };
+            depth += 1;
+            i += 1;
+            continue;
+        } else {
+            let end = if chr == b'i' { read_int(bytes, i) } else { read_str(bytes, i) };
+            match end {
+                Some(end) => i = end,
+                None => return false,
+            }
+        }
+
+        // A complete value ends here
+        if depth == 0 {
+            return i == bytes.len();
+        }
+        if stack[depth - 1] == DICT_VALUE {
+            stack[depth - 1] = DICT_KEY;
+        }
+    }
+}
+
+// Returns the offset right after the integer starting at `start`
+const fn read_int(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    let negative = i < bytes.len() && bytes[i] == b'-';
+    if negative {
+        i += 1;
+    }
+    if i >= bytes.len() || !bytes[i].is_ascii_digit() || (negative && bytes[i] == b'0') {
+        return None;
+    }
+    let limit = if negative { i64::MAX as u64 + 1 } else { i64::MAX as u64 };
+    let mut acc: u64 = 0;
+    while i < bytes.len() && bytes[i].is_ascii_digit() {
+        acc = match acc.checked_mul(10) {
+            Some(acc) => match acc.checked_add((bytes[i] - b'0') as u64) {
+                Some(acc) => acc,
+                None => return None,
+            },
+            None => return None,
+        };
+        if acc > limit {
+            return None;
+        }
+        i += 1;
+    }
+    if i < bytes.len() && bytes[i] == b'e' {
+        Some(i + 1)
+    } else {
+        None
+    }
+}
+
+// Returns the offset right after the string starting at `start`
+const fn read_str(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start;
+    let mut len: usize = 0;
+    while i < bytes.len() && bytes[i].is_ascii_digit() {
+        len = match len.checked_mul(10) {
+            Some(len) => match len.checked_add((bytes[i] - b'0') as usize) {
+                Some(len) => len,
+                None => return None,
+            },
+            None => return None,
+        };
+        i += 1;
+    }
+    if i == start || i >= bytes.len() || bytes[i] != b':' {
+        return None;
+    }
+    match (i + 1).checked_add(len) {
+        Some(end) if end <= bytes.len() => Some(end),
+        _ => None,
+    }
+}
+
+// Embeds a bencoded string literal as `&'static [u8]`, failing the build if it
+// is not a single well-formed value.
+#[macro_export]
+macro_rules! bencode_bytes {
+    ($lit:literal) => {{
+        const BYTES: &[u8] = $lit.as_bytes();
+        const _: () = assert!($crate::literal::is_valid(BYTES), concat!("invalid bencode literal: ", $lit));
+        BYTES
+    }};
+}
+
+// Builds a BEncodingType from JSON-like syntax:
+//
+//     bencode!({"announce": url, "info": {"length": 1234, "files": [1, 2]}})
+//
+// Dictionary keys are string or byte string literals, and keep the order they
+// are written in. Any other value goes through ToBencode.
+#[macro_export]
+macro_rules! bencode {
+    ([ $($items:tt)* ]) => {
+        $crate::bdecode::BEncodingType::List($crate::bencode!(@list [] $($items)*))
+    };
+    ({ $($entries:tt)* }) => {{
+        #[allow(unused_mut)]
+        let mut dict = $crate::convert::Dict::new();
+        $crate::bencode!(@dict dict $($entries)*);
+        $crate::bdecode::BEncodingType::Dictionary(dict)
+    }};
+
+    (@list [$($done:expr,)*]) => {
+        vec![$($done,)*]
+    };
+    (@list [$($done:expr,)*] [ $($items:tt)* ] $(, $($rest:tt)*)?) => {
+        $crate::bencode!(@list [$($done,)* $crate::bencode!([$($items)*]),] $($($rest)*)?)
+    };
+    (@list [$($done:expr,)*] { $($entries:tt)* } $(, $($rest:tt)*)?) => {
+        $crate::bencode!(@list [$($done,)* $crate::bencode!({$($entries)*}),] $($($rest)*)?)
+    };
+    (@list [$($done:expr,)*] $next:expr $(, $($rest:tt)*)?) => {
+        $crate::bencode!(@list [$($done,)* $crate::bencode!($next),] $($($rest)*)?)
+    };
+
+    (@dict $dict:ident) => {};
+    (@dict $dict:ident $key:literal : [ $($items:tt)* ] $(, $($rest:tt)*)?) => {
+        $crate::bencode!(@insert $dict $key, $crate::bencode!([$($items)*]));
+        $crate::bencode!(@dict $dict $($($rest)*)?);
+    };
+    (@dict $dict:ident $key:literal : { $($entries:tt)* } $(, $($rest:tt)*)?) => {
+        $crate::bencode!(@insert $dict $key, $crate::bencode!({$($entries)*}));
+        $crate::bencode!(@dict $dict $($($rest)*)?);
+    };
+    (@dict $dict:ident $key:literal : $value:expr $(, $($rest:tt)*)?) => {
+        $crate::bencode!(@insert $dict $key, $crate::bencode!($value));
+        $crate::bencode!(@dict $dict $($($rest)*)?);
+    };
+    (@insert $dict:ident $key:literal, $value:expr) => {
+        $dict.insert($crate::bytestring::ByteString(::std::convert::AsRef::<[u8]>::as_ref($key).to_vec()), $value);
+    };
+
+    ($value:expr) => {
+        $crate::convert::ToBencode::to_bencode(&$value)
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accepts_well_formed_values() {
+        assert!(is_valid(b"i0e"));
+        assert!(is_valid(b"i-9223372036854775808e"));
+        assert!(is_valid(b"i9223372036854775807e"));
+        assert!(is_valid(b"0:"));
+        assert!(is_valid(b"3:abc"));
+        assert!(is_valid(b"le"));
+        assert!(is_valid(b"de"));
+        assert!(is_valid(b"llleelleee"));
+        assert!(is_valid(b"d5:innerd1:ai345e1:b3:wowe6:inner2dee"));
+        assert!(is_valid(b"d1:ad2:xyd20:abcdefghij0123456789i555eeee"));
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        assert!(!is_valid(b""));
+        assert!(!is_valid(b"i-0e"));
+        assert!(!is_valid(b"ie"));
+        assert!(!is_valid(b"i12"));
+        assert!(!is_valid(b"i9223372036854775808e"));
+        assert!(!is_valid(b"i18446744073709551619e"));
+        assert!(!is_valid(b"3:ab"));
+        assert!(!is_valid(b"3abc"));
+        assert!(!is_valid(b"99999999999999999999999:a"));
+        assert!(!is_valid(b"l3:abc"));
+        assert!(!is_valid(b"d4:iteme"));
+        assert!(!is_valid(b"di1ei2ee"));
+        assert!(!is_valid(b"i1ei2e"));
+        assert!(!is_valid(b"e"));
+    }
+
+    #[test]
+    fn rejects_excessive_nesting() {
+        let mut nested = vec![b'l'; MAX_DEPTH];
+        nested.extend(vec![b'e'; MAX_DEPTH]);
+        assert!(is_valid(&nested));
+
+        let mut nested = vec![b'l'; MAX_DEPTH + 1];
+        nested.extend(vec![b'e'; MAX_DEPTH + 1]);
+        assert!(!is_valid(&nested));
+    }
+
+    #[test]
+    fn macro_builds_values() {
+        use crate::bdecode::decode;
+
+        let url = "http://tracker/announce".to_string();
+        let files = vec![1i64, 2];
+        let value = bencode!({
+            "announce": url,
+            "info": {"length": 1234, "name": "a", b"pieces": b"\x00\x01", "files": files},
+            "list": [[], {}, -1, ["x", [3]]],
+            "empty": {},
+        });
+        let expected = b"d8:announce23:http://tracker/announce4:infod6:lengthi1234e4:name1:a6:pieces2:\x00\x015:filesli1ei2eee\
+                         4:listlledei-1el1:xli3eeee5:emptydee";
+        assert_eq!(decode(expected).unwrap(), value);
+        assert_eq!(value, bencode!(value));
+        assert_eq!(decode(b"li5ee").unwrap(), bencode!([2 + 3]));
+        assert_eq!(decode(b"le").unwrap(), bencode!([]));
+    }
+
+    #[test]
+    fn macro_embeds_bytes() {
+        const PING: &[u8] = bencode_bytes!("d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe");
+        assert_eq!(b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe", PING);
+    }
+}
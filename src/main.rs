@@ -1,14 +1,13 @@
+// Unsafe code is only allowed when explicitly opted into.
+#![cfg_attr(not(feature = "unsafe-opt"), forbid(unsafe_code))]
 
-mod bdecode;
-mod bencode;
-mod error;
-mod bytestring;
+use domenec::{decode, encode};
 
 fn main() {
     let inp = b"d1:ad2:xyd20:abcdefghij0123456789i555eeee";
-    let decoded = bdecode::decode(inp).unwrap();
+    let decoded = decode(inp).unwrap();
     println!("Decoded => {:?}", decoded);
 
-    let encoded = bencode::encode(decoded);
+    let encoded = encode(decoded);
     println!("Reencoded=> {:?}", String::from_utf8(encoded))
 }
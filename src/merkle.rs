@@ -0,0 +1,210 @@
+use std::io::{self, Read};
+
+use sha2::{Digest, Sha256};
+
+// BEP 52 hashes files in 16 KiB blocks
+pub const BLOCK_SIZE: usize = 16 * 1024;
+
+pub type Hash = [u8; 32];
+
+// Merkle tree of a single file as defined by BEP 52. Leaves are the SHA-256 of
+// each 16 KiB block (the last one may be shorter). The leaf layer is padded
+// with zero hashes up to a power of two.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MerkleTree {
+    // layers[0] holds the padded leaves, the last layer holds only the root
+    layers: Vec<Vec<Hash>>,
+    blocks: usize,
+}
+
+impl MerkleTree {
+    pub fn from_bytes(bytes: &[u8]) -> MerkleTree {
+        MerkleTree::from_leaves(bytes.chunks(BLOCK_SIZE).map(sha256).collect())
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<MerkleTree> {
+        let mut leaves = Vec::new();
+        let mut block = vec![0; BLOCK_SIZE];
+        loop {
+            let len = read_block(&mut reader, &mut block)?;
+            if len == 0 {
+                break;
+            }
+            leaves.push(sha256(&block[..len]));
+            if len < BLOCK_SIZE {
+                break;
+            }
+        }
+        Ok(MerkleTree::from_leaves(leaves))
+    }
+
+    fn from_leaves(mut leaves: Vec<Hash>) -> MerkleTree {
+        let blocks = leaves.len();
+        leaves.resize(blocks.max(1).next_power_of_two(), [0; 32]);
+        let mut layers = vec![leaves];
+        while let Some(layer) = layers.last().filter(|layer| layer.len() > 1) {
+            let parents = layer.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+            layers.push(parents);
+        }
+        MerkleTree { layers, blocks }
+    }
+
+    // The `pieces root` of the file. An empty file has an all-zero root, but
+    // BEP 52 leaves such files without a root in the torrent.
+    pub fn root(&self) -> Hash {
+        self.layers[self.layers.len() - 1][0]
+    }
+
+    // Number of 16 KiB blocks in the file, ignoring padding
+    pub fn blocks(&self) -> usize {
+        self.blocks
+    }
+
+    // The hashes that go into `piece layers` for this file: one per piece that
+    // holds file data. Files no larger than one piece have no piece layer, as
+    // their root already is the piece hash. Returns None if `piece_length` is
+    // not a power of two of at least BLOCK_SIZE.
+    pub fn piece_layer(&self, piece_length: usize) -> Option<&[Hash]> {
+        let level = piece_level(piece_length)?;
+        let pieces = self.blocks.div_ceil(piece_length / BLOCK_SIZE);
+        if pieces <= 1 {
+            return Some(&[]);
+        }
+        Some(&self.layers[level][..pieces])
+    }
+
+    // Sibling hashes needed to prove that `piece` belongs under the root,
+    // ordered from the piece layer upwards.
+    pub fn proof(&self, piece_length: usize, piece: usize) -> Option<Vec<Hash>> {
+        let level = piece_level(piece_length)?;
+        if level >= self.layers.len() || piece >= self.layers[level].len() {
+            return None;
+        }
+        let mut pos = piece;
+        let proof = self.layers[level..self.layers.len() - 1].iter()
+            .map(|layer| {
+                let sibling = layer[pos ^ 1];
+                pos /= 2;
+                sibling
+            })
+            .collect();
+        Some(proof)
+    }
+}
+
+// Checks a proof produced by MerkleTree::proof against a known root
+pub fn verify_proof(piece_hash: &Hash, piece: usize, proof: &[Hash], root: &Hash) -> bool {
+    let mut pos = piece;
+    let computed = proof.iter().fold(*piece_hash, |hash, sibling| {
+        let parent = if pos % 2 == 0 { hash_pair(&hash, sibling) } else { hash_pair(sibling, &hash) };
+        pos /= 2;
+        parent
+    });
+    &computed == root
+}
+
+// Root of the merkle tree of a file read from `reader`
+pub fn root<R: Read>(reader: R) -> io::Result<Hash> {
+    MerkleTree::from_reader(reader).map(|tree| tree.root())
+}
+
+fn piece_level(piece_length: usize) -> Option<usize> {
+    if piece_length < BLOCK_SIZE || !piece_length.is_power_of_two() {
+        return None;
+    }
+    Some((piece_length / BLOCK_SIZE).trailing_zeros() as usize)
+}
+
+// Fills `block` as far as the reader allows, returning how much was read
+fn read_block<R: Read>(reader: &mut R, block: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < block.len() {
+        match reader.read(&mut block[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(len)
+}
+
+fn sha256(bytes: &[u8]) -> Hash {
+    Sha256::digest(bytes).into()
+}
+
+fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn single_block_root_is_its_hash() {
+        let bytes = data(1000);
+        let tree = MerkleTree::from_bytes(&bytes);
+        assert_eq!(sha256(&bytes), tree.root());
+        assert_eq!(1, tree.blocks());
+        assert_eq!(tree.root(), root(bytes.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn pads_leaves_with_zero_hashes() {
+        let bytes = data(2 * BLOCK_SIZE + 10);
+        let leaves: Vec<Hash> = bytes.chunks(BLOCK_SIZE).map(sha256).collect();
+        let expected = hash_pair(&hash_pair(&leaves[0], &leaves[1]), &hash_pair(&leaves[2], &[0; 32]));
+
+        let tree = MerkleTree::from_bytes(&bytes);
+        assert_eq!(expected, tree.root());
+        assert_eq!(3, tree.blocks());
+        assert_eq!(tree, MerkleTree::from_reader(bytes.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn empty_file_has_zero_root() {
+        assert_eq!([0; 32], MerkleTree::from_bytes(b"").root());
+        assert_eq!(0, MerkleTree::from_bytes(b"").blocks());
+    }
+
+    #[test]
+    fn piece_layers() {
+        let bytes = data(5 * BLOCK_SIZE);
+        let tree = MerkleTree::from_bytes(&bytes);
+        let leaves: Vec<Hash> = bytes.chunks(BLOCK_SIZE).map(sha256).collect();
+
+        assert_eq!(Some(leaves.as_slice()), tree.piece_layer(BLOCK_SIZE));
+        let layer = tree.piece_layer(2 * BLOCK_SIZE).unwrap();
+        assert_eq!(3, layer.len());
+        assert_eq!(hash_pair(&leaves[4], &[0; 32]), layer[2]);
+        assert_eq!(2, tree.piece_layer(4 * BLOCK_SIZE).unwrap().len());
+        assert_eq!(Some([].as_slice()), tree.piece_layer(8 * BLOCK_SIZE));
+        assert_eq!(Some([].as_slice()), tree.piece_layer(64 * BLOCK_SIZE));
+        assert_eq!(None, tree.piece_layer(3 * BLOCK_SIZE));
+        assert_eq!(None, tree.piece_layer(BLOCK_SIZE / 2));
+    }
+
+    #[test]
+    fn proofs_verify_against_root() {
+        let tree = MerkleTree::from_bytes(&data(7 * BLOCK_SIZE + 1));
+        let piece_length = 2 * BLOCK_SIZE;
+        let layer = tree.piece_layer(piece_length).unwrap().to_vec();
+
+        for (piece, hash) in layer.iter().enumerate() {
+            let proof = tree.proof(piece_length, piece).unwrap();
+            assert_eq!(2, proof.len());
+            assert!(verify_proof(hash, piece, &proof, &tree.root()));
+            assert!(!verify_proof(hash, piece ^ 1, &proof, &tree.root()));
+        }
+        assert_eq!(None, tree.proof(piece_length, 4));
+        assert_eq!(None, tree.proof(32 * BLOCK_SIZE, 0));
+    }
+}
@@ -0,0 +1,206 @@
+// Peer id generation and client detection for the two common conventions.
+//
+// Azureus style ids look like `-TR2940-xxxxxxxxxxxx`: a two letter client code
+// and four version characters between dashes. Shadow style ids look like
+// `S58B-----xxxxxxxxxxx`: a single client character, up to five version
+// characters padded with dashes, then `---`. The rest of the id is random.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+
+pub type PeerId = [u8; 20];
+
+// Version characters encode 0-9, then A-Z as 10-35 and a-z as 36-61
+const VERSION_ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+const AZUREUS_CLIENTS: &[(&str, &str)] = &[
+    ("AZ", "Vuze"),
+    ("BC", "BitComet"),
+    ("BI", "BiglyBT"),
+    ("BT", "BitTorrent"),
+    ("DE", "Deluge"),
+    ("FD", "Free Download Manager"),
+    ("KT", "KTorrent"),
+    ("LT", "libtorrent"),
+    ("lt", "rtorrent"),
+    ("qB", "qBittorrent"),
+    ("TR", "Transmission"),
+    ("UM", "µTorrent Mac"),
+    ("UT", "µTorrent"),
+    ("WW", "WebTorrent"),
+];
+
+const SHADOW_CLIENTS: &[(u8, &str)] = &[
+    (b'A', "ABC"),
+    (b'O', "Osprey Permaseed"),
+    (b'Q', "BTQueue"),
+    (b'R', "Tribler"),
+    (b'S', "Shadow"),
+    (b'T', "BitTornado"),
+    (b'U', "UPnP NAT Bit Torrent"),
+];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Style {
+    Azureus,
+    Shadow,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ClientInfo {
+    pub style: Style,
+    // Client code as found in the id, e.g. "TR" or "S"
+    pub code: String,
+    // Human readable client name if the code is a known one
+    pub name: Option<&'static str>,
+    // Decoded version characters, most significant first
+    pub version: Vec<u8>,
+}
+
+impl fmt::Display for ClientInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name.unwrap_or(&self.code))?;
+        for (i, part) in self.version.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " " } else { "." }, part)?;
+        }
+        Ok(())
+    }
+}
+
+// Builds an Azureus style id. Version parts must be below 62. Returns None if
+// the code is not alphanumeric or a version part is out of range.
+pub fn azureus(code: [u8; 2], version: [u8; 4]) -> Option<PeerId> {
+    if !code.iter().all(u8::is_ascii_alphanumeric) {
+        return None;
+    }
+    let mut id = [0; 20];
+    id[0] = b'-';
+    id[1..3].copy_from_slice(&code);
+    for (slot, &part) in id[3..7].iter_mut().zip(&version) {
+        *slot = *VERSION_ALPHABET.get(part as usize)?;
+    }
+    id[7] = b'-';
+    fill_random(&mut id[8..]);
+    Some(id)
+}
+
+// Builds a Shadow style id with at most five version parts, each below 62.
+pub fn shadow(code: u8, version: &[u8]) -> Option<PeerId> {
+    if !code.is_ascii_alphanumeric() || version.len() > 5 {
+        return None;
+    }
+    let mut id = [b'-'; 20];
+    id[0] = code;
+    for (slot, &part) in id[1..].iter_mut().zip(version) {
+        *slot = *VERSION_ALPHABET.get(part as usize)?;
+    }
+    fill_random(&mut id[9..]);
+    Some(id)
+}
+
+// Detects the client that generated `id`. Azureus style ids are recognized
+// even for unknown client codes; Shadow style ids only for known clients, as
+// the format is too loose to tell apart from random bytes otherwise.
+pub fn parse(id: &[u8]) -> Option<ClientInfo> {
+    if id.len() != 20 {
+        return None;
+    }
+    parse_azureus(id).or_else(|| parse_shadow(id))
+}
+
+fn parse_azureus(id: &[u8]) -> Option<ClientInfo> {
+    if id[0] != b'-' || id[7] != b'-' || !id[1..3].iter().all(u8::is_ascii_alphanumeric) {
+        return None;
+    }
+    let version = id[3..7].iter().map(|&chr| version_part(chr)).collect::<Option<_>>()?;
+    let code = String::from_utf8_lossy(&id[1..3]).into_owned();
+    let name = AZUREUS_CLIENTS.iter().find(|(known, _)| *known == code).map(|&(_, name)| name);
+    Some(ClientInfo { style: Style::Azureus, code, name, version })
+}
+
+fn parse_shadow(id: &[u8]) -> Option<ClientInfo> {
+    let name = SHADOW_CLIENTS.iter().find(|(known, _)| *known == id[0]).map(|&(_, name)| name)?;
+    if &id[6..9] != b"---" {
+        return None;
+    }
+    let len = id[1..6].iter().position(|&chr| chr == b'-').unwrap_or(5);
+    if id[1 + len..6].iter().any(|&chr| chr != b'-') {
+        return None;
+    }
+    let version = id[1..1 + len].iter().map(|&chr| version_part(chr)).collect::<Option<_>>()?;
+    Some(ClientInfo { style: Style::Shadow, code: (id[0] as char).to_string(), name: Some(name), version })
+}
+
+fn version_part(chr: u8) -> Option<u8> {
+    VERSION_ALPHABET.iter().position(|&c| c == chr).map(|pos| pos as u8)
+}
+
+// Fills `out` with random alphanumeric characters. Seeded from the standard
+// library's hash keys, which is plenty for peer ids but not for secrets.
+fn fill_random(out: &mut [u8]) {
+    let state = RandomState::new();
+    for (i, slot) in out.iter_mut().enumerate() {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(i);
+        *slot = VERSION_ALPHABET[(hasher.finish() % VERSION_ALPHABET.len() as u64) as usize];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_azureus_ids() {
+        let info = parse(b"-TR2940-k8hj0wgej6ch").unwrap();
+        assert_eq!(Style::Azureus, info.style);
+        assert_eq!("TR", info.code);
+        assert_eq!(Some("Transmission"), info.name);
+        assert_eq!(vec![2, 9, 4, 0], info.version);
+        assert_eq!("Transmission 2.9.4.0", info.to_string());
+
+        let info = parse(b"-XX1A00-k8hj0wgej6ch").unwrap();
+        assert_eq!(None, info.name);
+        assert_eq!(vec![1, 10, 0, 0], info.version);
+        assert_eq!("XX 1.10.0.0", info.to_string());
+    }
+
+    #[test]
+    fn parses_shadow_ids() {
+        let info = parse(b"S58B-----k8hj0wgej6c").unwrap();
+        assert_eq!(Style::Shadow, info.style);
+        assert_eq!("S", info.code);
+        assert_eq!(vec![5, 8, 11], info.version);
+        assert_eq!("Shadow 5.8.11", info.to_string());
+
+        assert_eq!(vec![0, 3, 6, 1, 0], parse(b"T03610---k8hj0wgej6c").unwrap().version);
+    }
+
+    #[test]
+    fn rejects_unknown_layouts() {
+        assert_eq!(None, parse(b"-TR2940-"));
+        assert_eq!(None, parse(b"M4-3-6--k8hj0wgej6ch"));
+        assert_eq!(None, parse(b"-T\x002940-k8hj0wgej6ch"));
+        assert_eq!(None, parse(b"S58-B----k8hj0wgej6c"));
+        assert_eq!(None, parse(b"X58B-----k8hj0wgej6c"));
+        assert_eq!(None, parse(&[0; 20]));
+    }
+
+    #[test]
+    fn generated_ids_round_trip() {
+        let id = azureus(*b"DE", [2, 1, 1, 0]).unwrap();
+        assert_eq!(b"-DE2110-", &id[..8]);
+        assert!(id.iter().all(u8::is_ascii_graphic));
+        assert_eq!("Deluge 2.1.1.0", parse(&id).unwrap().to_string());
+
+        let id = shadow(b'T', &[0, 3, 6]).unwrap();
+        assert_eq!(b"T036-----", &id[..9]);
+        assert_eq!(vec![0, 3, 6], parse(&id).unwrap().version);
+
+        assert_ne!(azureus(*b"DE", [2, 1, 1, 0]), azureus(*b"DE", [2, 1, 1, 0]));
+        assert_eq!(None, azureus(*b"D-", [0; 4]));
+        assert_eq!(None, azureus(*b"DE", [62, 0, 0, 0]));
+        assert_eq!(None, shadow(b'T', &[1; 6]));
+    }
+}
@@ -0,0 +1,312 @@
+// Push style decoding for data that arrives in pieces, such as bencode read
+// off a socket. Chunks are fed in as they come and a value is handed out once
+// it is complete.
+//
+// Incomplete input is recognized by the decoder failing exactly at the end of
+// what has been buffered so far. Lists and dictionaries being received are
+// kept on a stack together with their finished items, at every depth, and
+// decoding resumes after the last finished item, key or container start. Only
+// a string or integer still being received is parsed again on the next feed,
+// so feeding a large document in small chunks stays linear.
+// The buffer is capped, as a peer announcing a huge string and trickling it
+// in would otherwise grow it without bound.
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::bdecode::{BDecoder, BEncodingType, MAX_DEPTH};
+use crate::bytestring::ByteString;
+use crate::error::DecodingError;
+
+type Result<T> = std::result::Result<T, DecodingError>;
+
+// Buffer cap used unless set with max_buffered
+pub const DEFAULT_MAX_BUFFERED: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum DecodeProgress {
+    // The buffered bytes are a valid prefix, but the value is not complete
+    NeedMoreData,
+    Done(BEncodingType),
+}
+
+// Container being received, with its items completed so far
+#[derive(Debug)]
+enum Partial {
+    List(Vec<BEncodingType>),
+    // `key` is set while the value for that key is being received
+    Dict { entries: LinkedHashMap<ByteString, BEncodingType>, key: Option<ByteString> },
+}
+
+impl Partial {
+    fn into_value(self) -> BEncodingType {
+        match self {
+            Partial::List(list) => BEncodingType::List(list),
+            Partial::Dict { entries, .. } => BEncodingType::Dictionary(entries),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    max_buffered: usize,
+    // Open containers, outermost first
+    stack: Vec<Partial>,
+    // Offset in `buf` right after what `stack` holds
+    resume: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder { buf: Vec::new(), max_buffered: DEFAULT_MAX_BUFFERED, stack: Vec::new(), resume: 0 }
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    // Most bytes held at once, including those of finished values not yet
+    // handed out. Feeding past it fails with SizeLimitExceeded.
+    pub fn max_buffered(mut self, max_buffered: usize) -> Self {
+        self.max_buffered = max_buffered;
+        self
+    }
+
+    // Appends `bytes` and tries to decode the next value. Bytes following a
+    // finished value stay buffered for the next call, so several messages in
+    // one chunk are drained with `feed(&[])`. Errors leave the buffer as is;
+    // call `clear` to start over.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<DecodeProgress> {
+        if self.buf.len() + bytes.len() > self.max_buffered {
+            return Err(DecodingError::SizeLimitExceeded(self.max_buffered));
+        }
+        self.buf.extend_from_slice(bytes);
+        if self.buf.is_empty() {
+            return Ok(DecodeProgress::NeedMoreData);
+        }
+
+        match self.step()? {
+            Some((value, consumed)) => {
+                self.buf.drain(..consumed);
+                self.resume = 0;
+                Ok(DecodeProgress::Done(value))
+            }
+            None => Ok(DecodeProgress::NeedMoreData),
+        }
+    }
+
+    // Decodes as far as the buffer allows, returning the value and its length
+    // once it is complete
+    fn step(&mut self) -> Result<Option<(BEncodingType, usize)>> {
+        let Decoder { buf, stack, resume, .. } = self;
+        let mut decoder = BDecoder::new(buf);
+
+        loop {
+            decoder.seek_to(*resume);
+            let Ok(next) = decoder.peek() else {
+                return Ok(None);
+            };
+            let value = match stack.last_mut() {
+                Some(Partial::Dict { key: key @ None, .. }) if next != b'e' => {
+                    match decoder.read_str_span() {
+                        Ok(span) => *key = Some(decoder.str_at(span)),
+                        Err(err) => return truncated(decoder.cursor() == buf.len(), stack, err),
+                    }
+                    *resume = decoder.cursor();
+                    continue;
+                }
+                Some(Partial::Dict { key: key @ Some(_), .. }) if next == b'e' => {
+                    let key = key.take().expect("matched Some");
+                    return Err(in_open_values(stack, DecodingError::KeyWithoutValue(key)));
+                }
+                Some(_) if next == b'e' => {
+                    *resume += 1;
+                    stack.pop().expect("matched Some").into_value()
+                }
+                _ if next == b'l' || next == b'd' => {
+                    if stack.len() == MAX_DEPTH {
+                        return Err(DecodingError::NestingTooDeep(MAX_DEPTH));
+                    }
+                    stack.push(if next == b'l' {
+                        Partial::List(Vec::new())
+                    } else {
+                        Partial::Dict { entries: LinkedHashMap::new(), key: None }
+                    });
+                    *resume += 1;
+                    continue;
+                }
+                _ => match decoder.parse_type() {
+                    Ok(value) => {
+                        *resume = decoder.cursor();
+                        value
+                    }
+                    Err(err) => return truncated(decoder.cursor() == buf.len(), stack, err),
+                },
+            };
+
+            match stack.last_mut() {
+                None => return Ok(Some((value, *resume))),
+                Some(Partial::List(list)) => list.push(value),
+                Some(Partial::Dict { entries, key }) => {
+                    entries.insert(key.take().expect("values follow their key"), value);
+                }
+            }
+        }
+    }
+
+    // Bytes received but not yet handed out as part of a value
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.stack.clear();
+        self.resume = 0;
+    }
+}
+
+// Waits for more data if `err` only means that the buffer, which the decoder
+// stopped at the end of, is cut short. Reports it as decode would otherwise.
+fn truncated(at_end: bool, stack: &[Partial], err: DecodingError) -> Result<Option<(BEncodingType, usize)>> {
+    // A string length cut off after its '-' is reported without the EndOfFile
+    if at_end && matches!(err, DecodingError::EndOfFile | DecodingError::StringWithoutLength) {
+        Ok(None)
+    } else {
+        Err(in_open_values(stack, err))
+    }
+}
+
+// Attributes an error to the keys of the dictionaries being received, the way
+// decode attributes it to the enclosing keys
+fn in_open_values(stack: &[Partial], err: DecodingError) -> DecodingError {
+    stack.iter().rev().fold(err, |err, partial| match partial {
+        Partial::Dict { key: Some(key), .. } => err.in_value_of(key.clone()),
+        _ => err,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bdecode::decode;
+    use crate::bytestring::ToByteString;
+    use crate::test_util::fuzz_inputs;
+
+    use super::*;
+
+    #[test]
+    fn decodes_across_chunks() {
+        let msg = b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe";
+        for split in 1..msg.len() {
+            let mut decoder = Decoder::new();
+            assert_eq!(Ok(DecodeProgress::NeedMoreData), decoder.feed(&msg[..split]), "split at {}", split);
+            assert_eq!(Ok(DecodeProgress::Done(decode(msg).unwrap())), decoder.feed(&msg[split..]));
+            assert!(decoder.buffered().is_empty());
+        }
+    }
+
+    #[test]
+    fn keeps_bytes_after_a_value() {
+        let mut decoder = Decoder::new();
+        assert_eq!(Ok(DecodeProgress::Done(BEncodingType::Integer(1))), decoder.feed(b"i1e3:a"));
+        assert_eq!(b"3:a", decoder.buffered());
+        assert_eq!(Ok(DecodeProgress::NeedMoreData), decoder.feed(&[]));
+        assert_eq!(Ok(DecodeProgress::Done(BEncodingType::String("abc".to_byte_string()))), decoder.feed(b"bc"));
+        assert_eq!(Ok(DecodeProgress::NeedMoreData), decoder.feed(&[]));
+    }
+
+    #[test]
+    fn resumes_after_finished_items() {
+        let msg = b"d1:ali1ei2ee1:bd1:ci3ee1:d5:hello1:ei-4ee";
+        let mut decoder = Decoder::new();
+        let mut resumed = Vec::new();
+        for (i, byte) in msg.iter().enumerate() {
+            let progress = decoder.feed(&[*byte]).unwrap();
+            resumed.push(decoder.resume);
+            if i + 1 < msg.len() {
+                assert_eq!(DecodeProgress::NeedMoreData, progress);
+            } else {
+                assert_eq!(DecodeProgress::Done(decode(msg).unwrap()), progress);
+            }
+        }
+        // Each container start, key and finished value moves the resume point
+        // forward, at any depth
+        assert_eq!(vec![1, 4, 5, 8, 11, 12, 15, 16, 19, 22, 23, 26, 33, 36, 40], {
+            let mut points = resumed[..msg.len() - 1].to_vec();
+            points.dedup();
+            points
+        });
+    }
+
+    #[test]
+    fn bounds_work_for_nested_documents() {
+        let mut msg = b"d4:infod5:filesl".to_vec();
+        for i in 0..2000 {
+            msg.extend(format!("d6:lengthi{}e4:pathl8:file.txtee", i).as_bytes());
+        }
+        msg.extend(b"e4:name4:testee");
+
+        let mut decoder = Decoder::new();
+        let mut scanned = 0;
+        let mut chunks = msg.chunks(64).peekable();
+        while let Some(chunk) = chunks.next() {
+            // Everything after the resume point is parsed again by this feed
+            scanned += decoder.buffered().len() - decoder.resume + chunk.len();
+            let progress = decoder.feed(chunk).unwrap();
+            if chunks.peek().is_some() {
+                assert_eq!(DecodeProgress::NeedMoreData, progress);
+            } else {
+                assert_eq!(DecodeProgress::Done(decode(&msg).unwrap()), progress);
+            }
+        }
+        assert!(scanned < 2 * msg.len(), "scanned {} bytes for {}", scanned, msg.len());
+    }
+
+    #[test]
+    fn matches_decode_errors_when_trickled() {
+        let docs: &[&[u8]] = &[
+            b"d1:ae", b"d1:ad1:bi-0eee", b"li1e3x", b"ld1:ai1e1:bi99999999999999999999eee", b"d1:a1:b1:ce",
+            b"d1:ald1:bd1:ceeee", b"d1:ald1:bi1e2:xx-1:eee", b"ldi1ei2eee",
+        ];
+        for doc in docs {
+            let mut decoder = Decoder::new();
+            let res = doc.iter().map(|byte| decoder.feed(&[*byte])).find(|res| res != &Ok(DecodeProgress::NeedMoreData));
+            assert_eq!(Some(decode(doc).map(DecodeProgress::Done)), res, "{:?}", doc.to_byte_string());
+        }
+
+        // Inputs the decoder is still waiting on are left out, as decode
+        // reports those as truncated
+        for doc in fuzz_inputs(2_000, 30) {
+            let mut decoder = Decoder::new();
+            let res = doc.iter().map(|byte| decoder.feed(&[*byte])).find(|res| res != &Ok(DecodeProgress::NeedMoreData));
+            if let Some(res) = res {
+                assert_eq!(decode(&doc).map(DecodeProgress::Done), res, "{:?}", ByteString(doc.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn caps_the_buffer() {
+        let mut decoder = Decoder::new().max_buffered(16);
+        assert_eq!(Ok(DecodeProgress::NeedMoreData), decoder.feed(b"99999999999:"));
+        assert_eq!(Ok(DecodeProgress::NeedMoreData), decoder.feed(b"abcd"));
+        assert_eq!(Err(DecodingError::SizeLimitExceeded(16)), decoder.feed(b"e"));
+        assert_eq!(16, decoder.buffered().len());
+
+        decoder.clear();
+        assert_eq!(Ok(DecodeProgress::Done(BEncodingType::Integer(1))), decoder.feed(b"i1e"));
+    }
+
+    #[test]
+    fn reports_malformed_input() {
+        let mut decoder = Decoder::new();
+        assert_eq!(Ok(DecodeProgress::NeedMoreData), decoder.feed(b"d1:a"));
+        assert_eq!(Err(DecodingError::KeyWithoutValue("a".to_byte_string())), decoder.feed(b"e"));
+        decoder.clear();
+        assert_eq!(Err(DecodingError::NegativeStringLen), decoder.feed(b"-3"));
+        decoder.clear();
+        assert_eq!(Err(DecodingError::MissingIdentifier('e')), decoder.feed(b"i12x"));
+    }
+}
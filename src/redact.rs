@@ -0,0 +1,96 @@
+// Copies of values with sensitive parts masked, for logging messages that
+// carry passkeys, tokens or peer addresses.
+
+use crate::bdecode::BEncodingType;
+use crate::bytestring::ByteString;
+use crate::index::PathSegment;
+
+// Byte used to overwrite redacted strings
+pub const MASK: u8 = b'*';
+
+impl BEncodingType {
+    // Returns a copy with the values at `paths` masked. Strings keep their
+    // length but every byte becomes MASK, integers become a 1 followed by as
+    // many zeros as keep their digit count and sign, and containers are masked
+    // item by item so their shape stays visible. The encoded length never
+    // changes. Paths that don't exist are ignored.
+    pub fn redact(&self, paths: &[&[PathSegment]]) -> BEncodingType {
+        redact_value(self, paths.to_vec())
+    }
+}
+
+fn redact_value(value: &BEncodingType, paths: Vec<&[PathSegment]>) -> BEncodingType {
+    if paths.iter().any(|path| path.is_empty()) {
+        return mask(value);
+    }
+    // Paths continuing below `segment`
+    let below = |segment: PathSegment| -> Vec<&[PathSegment]> {
+        paths.iter().filter(|path| path[0] == segment).map(|path| &path[1..]).collect()
+    };
+    match value {
+        BEncodingType::Integer(i) => BEncodingType::Integer(*i),
+        BEncodingType::String(s) => BEncodingType::String(s.clone()),
+        BEncodingType::List(list) => BEncodingType::List(list.iter().enumerate()
+            .map(|(i, item)| redact_value(item, below(PathSegment::Index(i))))
+            .collect()),
+        BEncodingType::Dictionary(dict) => BEncodingType::Dictionary(dict.iter()
+            .map(|(key, item)| (key.clone(), redact_value(item, below(PathSegment::Key(&key.0)))))
+            .collect()),
+    }
+}
+
+fn mask(value: &BEncodingType) -> BEncodingType {
+    match value {
+        BEncodingType::Integer(i) => {
+            // 10^18 is the largest power of ten in an i64, and i64::MIN has 19 digits too
+            let masked = 10i64.pow(i.unsigned_abs().checked_ilog10().unwrap_or(0));
+            BEncodingType::Integer(if *i < 0 { -masked } else { masked })
+        }
+        BEncodingType::String(s) => BEncodingType::String(ByteString(vec![MASK; s.0.len()])),
+        BEncodingType::List(list) => BEncodingType::List(list.iter().map(mask).collect()),
+        BEncodingType::Dictionary(dict) => BEncodingType::Dictionary(dict.iter()
+            .map(|(key, item)| (key.clone(), mask(item)))
+            .collect()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bdecode::decode;
+    use crate::bencode::encode;
+
+    use super::*;
+
+    fn redact(inp: &[u8], paths: &[&[PathSegment]]) -> Vec<u8> {
+        encode(decode(inp).unwrap().redact(paths))
+    }
+
+    #[test]
+    fn masks_selected_values() {
+        let announce = b"d8:announce28:http://t.org/abc123/announce4:porti6881ee";
+        assert_eq!(b"d8:announce28:****************************4:porti6881ee".to_vec(),
+                   redact(announce, &[&["announce".into()]]));
+        assert_eq!(b"d8:announce28:****************************4:porti1000ee".to_vec(),
+                   redact(announce, &[&["announce".into()], &["port".into()]]));
+        assert_eq!(announce.to_vec(), redact(announce, &[&["missing".into()], &["port".into(), 0.into()]]));
+    }
+
+    #[test]
+    fn masks_nested_values_and_subtrees() {
+        let msg = b"d1:rd2:id2:ab6:valuesl6:\x01\x02\x03\x04\x1a\xe16:\x05\x06\x07\x08\x1a\xe1ee1:t2:aae";
+        assert_eq!(b"d1:rd2:id2:ab6:valuesl6:******6:\x05\x06\x07\x08\x1a\xe1ee1:t2:aae".to_vec(),
+                   redact(msg, &[&["r".into(), "values".into(), 0.into()]]));
+        assert_eq!(b"d1:rd2:id2:**6:valuesl6:******6:******ee1:t2:aae".to_vec(), redact(msg, &[&["r".into()]]));
+        assert_eq!(b"d1:rd2:id2:**6:valuesl6:******6:******ee1:t2:**e".to_vec(), redact(msg, &[&[]]));
+    }
+
+    #[test]
+    fn keeps_the_encoded_length() {
+        let ints = [0, 7, -7, 10, 99, -100, 6881, i64::MAX, i64::MIN, i64::MIN + 1];
+        let value = BEncodingType::List(ints.iter().map(|i| BEncodingType::Integer(*i)).collect());
+        let masked = value.redact(&[&[]]);
+        assert_eq!(encode(value).len(), encode(masked.clone()).len());
+        assert_eq!(b"li1ei1ei-1ei10ei10ei-100ei1000ei1000000000000000000ei-1000000000000000000ei-1000000000000000000ee".to_vec(),
+                   encode(masked));
+    }
+}
@@ -0,0 +1,414 @@
+// serde support for encoding: `to_bytes` and `to_writer` encode any
+// `Serialize` type.
+//
+// Values are built into a BEncodingType and written with encode_sorted_ref, so
+// dictionary keys always come out in spec order. The mapping mirrors the `de`
+// module: booleans become 0 and 1, unit the empty string, and `None` fields
+// are left out of their dictionary. Floats and non-string keys are rejected.
+
+use std::io::Write;
+
+use linked_hash_map::LinkedHashMap;
+use serde::ser::{self, Serialize};
+
+use crate::bdecode::BEncodingType;
+use crate::bencode::encode_sorted_ref;
+use crate::bytestring::{ByteString, ToByteString};
+use crate::error::SerdeError;
+
+type Result<T> = std::result::Result<T, SerdeError>;
+
+impl ser::Error for SerdeError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        SerdeError::Custom(msg.to_string())
+    }
+}
+
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    Ok(encode_sorted_ref(&to_value(value)?))
+}
+
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(mut writer: W, value: &T) -> Result<()> {
+    writer.write_all(&to_bytes(value)?).map_err(|err| SerdeError::Io(err.kind()))
+}
+
+fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<BEncodingType> {
+    value.serialize(Serializer)?.ok_or(SerdeError::Unsupported("a top level None"))
+}
+
+// Wraps the payload of a non-unit enum variant as `{variant: value}`
+fn variant_dict(variant: &'static str, value: BEncodingType) -> BEncodingType {
+    let mut dict = LinkedHashMap::new();
+    dict.insert(variant.to_byte_string(), value);
+    BEncodingType::Dictionary(dict)
+}
+
+// Serializes into a value, or None for a `None` that its parent should skip
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<BEncodingType>;
+    type Error = SerdeError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
+        Ok(Some(BEncodingType::Integer(v)))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok> {
+        i64::try_from(v).or(Err(SerdeError::Unsupported("an integer outside the i64 range")))
+            .and_then(|v| self.serialize_i64(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
+        self.serialize_i128(v as i128)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok> {
+        i128::try_from(v).or(Err(SerdeError::Unsupported("an integer outside the i64 range")))
+            .and_then(|v| self.serialize_i128(v))
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok> {
+        Err(SerdeError::Unsupported("a float"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok> {
+        Err(SerdeError::Unsupported("a float"))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok> {
+        Ok(Some(BEncodingType::String(v.to_byte_string())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok> {
+        Ok(Some(BEncodingType::String(v.to_byte_string())))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok> {
+        self.serialize_bytes(b"")
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Self::Ok> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok> {
+        Ok(Some(variant_dict(variant, to_value(value)?)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer> {
+        Ok(SeqSerializer { list: Vec::with_capacity(len.unwrap_or(0)), variant: None })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer> {
+        Ok(SeqSerializer { list: Vec::with_capacity(len), variant: Some(variant) })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer> {
+        Ok(MapSerializer { dict: LinkedHashMap::new(), key: None, variant: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer> {
+        Ok(MapSerializer { dict: LinkedHashMap::new(), key: None, variant: Some(variant) })
+    }
+}
+
+struct SeqSerializer {
+    list: Vec<BEncodingType>,
+    variant: Option<&'static str>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let value = value.serialize(Serializer)?.ok_or(SerdeError::Unsupported("None inside a list"))?;
+        self.list.push(value);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<BEncodingType>> {
+        let list = BEncodingType::List(self.list);
+        Ok(Some(match self.variant {
+            Some(variant) => variant_dict(variant, list),
+            None => list,
+        }))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Option<BEncodingType>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Option<BEncodingType>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Option<BEncodingType>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Option<BEncodingType>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    dict: LinkedHashMap<ByteString, BEncodingType>,
+    key: Option<ByteString>,
+    variant: Option<&'static str>,
+}
+
+impl MapSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: ByteString, value: &T) -> Result<()> {
+        if let Some(value) = value.serialize(Serializer)? {
+            self.dict.insert(key, value);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<BEncodingType>> {
+        let dict = BEncodingType::Dictionary(self.dict);
+        Ok(Some(match self.variant {
+            Some(variant) => variant_dict(variant, dict),
+            None => dict,
+        }))
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Option<BEncodingType>;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        match key.serialize(Serializer)? {
+            Some(BEncodingType::String(key)) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(SerdeError::Unsupported("a dictionary key that is not a string")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self.key.take().ok_or_else(|| SerdeError::Custom("value serialized before key".to_string()))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Option<BEncodingType>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.insert(key.to_byte_string(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Option<BEncodingType>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.insert(key.to_byte_string(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::de::from_bytes;
+
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Info {
+        name: String,
+        #[serde(rename = "piece length")]
+        piece_length: u32,
+        #[serde(with = "serde_bytes")]
+        pieces: Vec<u8>,
+        private: Option<bool>,
+        length: Option<u64>,
+    }
+
+    #[test]
+    fn serializes_structs_with_sorted_keys() {
+        let info = Info {
+            name: "a".to_string(),
+            piece_length: 16384,
+            pieces: vec![0, 255],
+            private: Some(true),
+            length: None,
+        };
+        let bytes = to_bytes(&info).unwrap();
+        assert_eq!(b"d4:name1:a12:piece lengthi16384e6:pieces2:\x00\xff7:privatei1ee".to_vec(), bytes);
+        assert_eq!(Ok(info), from_bytes(&bytes));
+
+        let mut out = Vec::new();
+        to_writer(&mut out, &HashMap::from([("b", 1), ("a", 2)])).unwrap();
+        assert_eq!(b"d1:ai2e1:bi1ee".to_vec(), out);
+    }
+
+    #[test]
+    fn serializes_collections_and_enums() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Msg {
+            Ping,
+            Value(i64),
+            Pair(String, String),
+            Error { code: u16 },
+        }
+
+        let msgs = vec![Msg::Ping, Msg::Value(3), Msg::Pair("a".to_string(), "b".to_string()), Msg::Error { code: 201 }];
+        let bytes = to_bytes(&msgs).unwrap();
+        assert_eq!(b"l4:Pingd5:Valuei3eed4:Pairl1:a1:beed5:Errord4:codei201eeee".to_vec(), bytes);
+        assert_eq!(Ok(msgs), from_bytes(&bytes));
+
+        assert_eq!(Ok(b"li-1e1:xe".to_vec()), to_bytes(&(-1, 'x')));
+        assert_eq!(Ok(b"0:".to_vec()), to_bytes(&()));
+    }
+
+    #[test]
+    fn rejects_unrepresentable_values() {
+        assert_eq!(Err(SerdeError::Unsupported("a float")), to_bytes(&1.5));
+        assert_eq!(Err(SerdeError::Unsupported("an integer outside the i64 range")), to_bytes(&u64::MAX));
+        assert_eq!(Err(SerdeError::Unsupported("a top level None")), to_bytes(&None::<i64>));
+        assert_eq!(Err(SerdeError::Unsupported("None inside a list")), to_bytes(&vec![Some(1), None]));
+        assert_eq!(Err(SerdeError::Unsupported("a dictionary key that is not a string")),
+                   to_bytes(&BTreeMap::from([(1, 2)])));
+    }
+}
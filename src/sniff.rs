@@ -0,0 +1,75 @@
+// Cheap detection of what a buffer holds, so tools can pick a sensible way to
+// render it without being told. Only the top level keys are looked at.
+
+use crate::index::{Kind, StructureIndex};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BencodeKind {
+    // Metainfo file: has an `info` dictionary
+    Torrent,
+    // DHT message: has the `t` transaction id and the `y` message type
+    Krpc,
+    // Announce or scrape response from an HTTP tracker
+    TrackerResponse,
+    // libtorrent style resume data
+    FastResume,
+    Dictionary,
+    List,
+    Integer,
+    String,
+}
+
+// Returns None unless `bytes` is exactly one well-formed bencoded value
+pub fn sniff(bytes: &[u8]) -> Option<BencodeKind> {
+    let index = StructureIndex::build(bytes).ok()?;
+    let root = index.nodes().first().filter(|root| root.end == bytes.len())?;
+    let kind = match root.kind {
+        Kind::Integer => BencodeKind::Integer,
+        Kind::String => BencodeKind::String,
+        Kind::List => BencodeKind::List,
+        Kind::Dictionary => {
+            let has = |key: &[u8]| index.get(0, key).is_some();
+            let is_dict = |key: &[u8]| index.get(0, key).is_some_and(|node| index.nodes()[node].kind == Kind::Dictionary);
+
+            if is_dict(b"info") {
+                BencodeKind::Torrent
+            } else if has(b"t") && has(b"y") {
+                BencodeKind::Krpc
+            } else if has(b"failure reason") || (has(b"interval") && has(b"peers")) || is_dict(b"files") {
+                BencodeKind::TrackerResponse
+            } else if has(b"file-format") || (has(b"info-hash") && has(b"save_path")) {
+                BencodeKind::FastResume
+            } else {
+                BencodeKind::Dictionary
+            }
+        }
+    };
+    Some(kind)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn guesses_document_kinds() {
+        assert_eq!(Some(BencodeKind::Torrent), sniff(b"d8:announce3:url4:infod4:name1:aee"));
+        assert_eq!(Some(BencodeKind::Krpc), sniff(b"d1:ad2:id2:aae1:q4:ping1:t2:aa1:y1:qe"));
+        assert_eq!(Some(BencodeKind::TrackerResponse), sniff(b"d8:intervali1800e5:peers0:e"));
+        assert_eq!(Some(BencodeKind::TrackerResponse), sniff(b"d14:failure reason3:bade"));
+        assert_eq!(Some(BencodeKind::TrackerResponse), sniff(b"d5:filesdee"));
+        assert_eq!(Some(BencodeKind::FastResume), sniff(b"d11:file-format22:libtorrent resume filee"));
+        assert_eq!(Some(BencodeKind::Dictionary), sniff(b"d4:info3:abce"));
+        assert_eq!(Some(BencodeKind::List), sniff(b"le"));
+        assert_eq!(Some(BencodeKind::Integer), sniff(b"i1e"));
+        assert_eq!(Some(BencodeKind::String), sniff(b"1:a"));
+    }
+
+    #[test]
+    fn rejects_non_bencode() {
+        assert_eq!(None, sniff(b""));
+        assert_eq!(None, sniff(b"{\"info\": {}}"));
+        assert_eq!(None, sniff(b"d4:infodee\n"));
+        assert_eq!(None, sniff(b"d4:infod"));
+    }
+}
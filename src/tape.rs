@@ -0,0 +1,347 @@
+use std::borrow::Cow;
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::bdecode::{BDecoder, BEncodingType};
+use crate::bytestring::ToByteString;
+use crate::error::DecodingError;
+
+type Result<T> = std::result::Result<T, DecodingError>;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TapeEntry {
+    Integer(i64),
+    // Span of the string content in the tape's byte storage
+    String { start: usize, end: usize },
+    // `next` is the position of the first entry after the container's subtree
+    List { len: usize, next: usize },
+    Dictionary { len: usize, next: usize },
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TapeNode {
+    pub entry: TapeEntry,
+    pub parent: Option<usize>,
+}
+
+// A document flattened into a single vector in document order, in the spirit
+// of simdjson's tape. Strings point back into the input instead of being
+// copied, and containers record where their subtree ends so siblings can be
+// reached in constant time. Dictionary entries are stored as a key String
+// directly followed by the value.
+//
+// A parsed tape borrows its input. A tape built from a BEncodingType owns a
+// buffer holding just the string contents.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Tape<'a> {
+    bytes: Cow<'a, [u8]>,
+    nodes: Vec<TapeNode>,
+}
+
+impl<'a> Tape<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Tape<'a>> {
+        let mut nodes = Vec::new();
+        write_value(&mut BDecoder::new(bytes), &mut nodes, None)?;
+        Ok(Tape { bytes: Cow::Borrowed(bytes), nodes })
+    }
+
+    pub fn nodes(&self) -> &[TapeNode] {
+        &self.nodes
+    }
+
+    pub fn root(&self) -> TapeCursor<'_, 'a> {
+        TapeCursor { tape: self, pos: 0 }
+    }
+
+    pub fn cursor_at(&self, pos: usize) -> Option<TapeCursor<'_, 'a>> {
+        (pos < self.nodes.len()).then_some(TapeCursor { tape: self, pos })
+    }
+
+    pub fn to_value(&self) -> BEncodingType {
+        self.root().to_value()
+    }
+}
+
+impl BEncodingType {
+    pub fn to_tape(&self) -> Tape<'static> {
+        let mut tape = Tape { bytes: Cow::Owned(Vec::new()), nodes: Vec::new() };
+        push_value(self, &mut tape, None);
+        tape
+    }
+}
+
+fn push_value(value: &BEncodingType, tape: &mut Tape, parent: Option<usize>) {
+    let pos = tape.nodes.len();
+    let entry = match value {
+        BEncodingType::Integer(i) => TapeEntry::Integer(*i),
+        BEncodingType::String(bs) => push_str(&bs.0, tape),
+        BEncodingType::List(list) => {
+            tape.nodes.push(TapeNode { entry: TapeEntry::List { len: 0, next: 0 }, parent });
+            for item in list {
+                push_value(item, tape, Some(pos));
+            }
+            TapeEntry::List { len: list.len(), next: tape.nodes.len() }
+        }
+        BEncodingType::Dictionary(dict) => {
+            tape.nodes.push(TapeNode { entry: TapeEntry::Dictionary { len: 0, next: 0 }, parent });
+            for (key, val) in dict.iter() {
+                let entry = push_str(&key.0, tape);
+                tape.nodes.push(TapeNode { entry, parent: Some(pos) });
+                push_value(val, tape, Some(pos));
+            }
+            TapeEntry::Dictionary { len: dict.len(), next: tape.nodes.len() }
+        }
+    };
+    if pos < tape.nodes.len() {
+        tape.nodes[pos].entry = entry;
+    } else {
+        tape.nodes.push(TapeNode { entry, parent });
+    }
+}
+
+fn push_str(bytes: &[u8], tape: &mut Tape) -> TapeEntry {
+    let buf = tape.bytes.to_mut();
+    let start = buf.len();
+    buf.extend_from_slice(bytes);
+    TapeEntry::String { start, end: buf.len() }
+}
+
+// A position on a tape with navigation to parents, children and siblings
+#[derive(Debug, Clone, Copy)]
+pub struct TapeCursor<'t, 'a> {
+    tape: &'t Tape<'a>,
+    pos: usize,
+}
+
+impl<'t, 'a> TapeCursor<'t, 'a> {
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn entry(&self) -> TapeEntry {
+        self.tape.nodes[self.pos].entry
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self.entry() {
+            TapeEntry::Integer(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&'t [u8]> {
+        match self.entry() {
+            TapeEntry::String { start, end } => Some(&self.tape.bytes[start..end]),
+            _ => None,
+        }
+    }
+
+    // Number of items in a list or entries in a dictionary
+    pub fn len(&self) -> Option<usize> {
+        match self.entry() {
+            TapeEntry::List { len, .. } | TapeEntry::Dictionary { len, .. } => Some(len),
+            _ => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    pub fn parent(&self) -> Option<Self> {
+        let pos = self.tape.nodes[self.pos].parent?;
+        Some(TapeCursor { tape: self.tape, pos })
+    }
+
+    pub fn first_child(&self) -> Option<Self> {
+        self.is_empty().filter(|empty| !empty)?;
+        Some(TapeCursor { tape: self.tape, pos: self.pos + 1 })
+    }
+
+    pub fn next_sibling(&self) -> Option<Self> {
+        let parent_end = self.parent()?.subtree_end();
+        let pos = self.subtree_end();
+        (pos < parent_end).then_some(TapeCursor { tape: self.tape, pos })
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = TapeCursor<'t, 'a>> {
+        std::iter::successors(self.first_child(), |child| child.next_sibling())
+    }
+
+    // Item `i` of a list
+    pub fn at(&self, i: usize) -> Option<Self> {
+        match self.entry() {
+            TapeEntry::List { .. } => self.children().nth(i),
+            _ => None,
+        }
+    }
+
+    // Value stored under `key` in a dictionary
+    pub fn get(&self, key: &[u8]) -> Option<Self> {
+        if !matches!(self.entry(), TapeEntry::Dictionary { .. }) {
+            return None;
+        }
+        let mut children = self.children();
+        while let (Some(k), Some(v)) = (children.next(), children.next()) {
+            if k.as_bytes() == Some(key) {
+                return Some(v);
+            }
+        }
+        None
+    }
+
+    // Builds the subtree under this cursor as a BEncodingType
+    pub fn to_value(self) -> BEncodingType {
+        match self.entry() {
+            TapeEntry::Integer(i) => BEncodingType::Integer(i),
+            TapeEntry::String { start, end } => BEncodingType::String((&self.tape.bytes[start..end]).to_byte_string()),
+            TapeEntry::List { .. } => BEncodingType::List(self.children().map(|child| child.to_value()).collect()),
+            TapeEntry::Dictionary { .. } => {
+                let mut dict = LinkedHashMap::new();
+                let mut children = self.children();
+                while let (Some(k), Some(v)) = (children.next(), children.next()) {
+                    dict.insert(k.as_bytes().unwrap_or_default().to_byte_string(), v.to_value());
+                }
+                BEncodingType::Dictionary(dict)
+            }
+        }
+    }
+
+    fn subtree_end(&self) -> usize {
+        match self.entry() {
+            TapeEntry::List { next, .. } | TapeEntry::Dictionary { next, .. } => next,
+            _ => self.pos + 1,
+        }
+    }
+}
+
+fn write_value(decoder: &mut BDecoder, nodes: &mut Vec<TapeNode>, parent: Option<usize>) -> Result<()> {
+    decoder.check_budget()?;
+    let pos = nodes.len();
+    let entry = match decoder.peek()? {
+        b'i' => TapeEntry::Integer(decoder.parse_int()?),
+        b'l' => {
+            nodes.push(TapeNode { entry: TapeEntry::List { len: 0, next: 0 }, parent });
+            let mut len = 0;
+            decoder.nested(|decoder| decoder.walk_list(|decoder| {
+                len += 1;
+                write_value(decoder, nodes, Some(pos))
+            }))?;
+            TapeEntry::List { len, next: nodes.len() }
+        }
+        b'd' => {
+            nodes.push(TapeNode { entry: TapeEntry::Dictionary { len: 0, next: 0 }, parent });
+            let mut len = 0;
+            decoder.nested(|decoder| decoder.walk_dict(|decoder, key| {
+                len += 1;
+                let entry = TapeEntry::String { start: key.content, end: key.end };
+                nodes.push(TapeNode { entry, parent: Some(pos) });
+                write_value(decoder, nodes, Some(pos))
+            }))?;
+            TapeEntry::Dictionary { len, next: nodes.len() }
+        }
+        _ => {
+            let span = decoder.read_str_span()?;
+            TapeEntry::String { start: span.content, end: span.end }
+        }
+    };
+    if pos < nodes.len() {
+        nodes[pos].entry = entry;
+    } else {
+        nodes.push(TapeNode { entry, parent });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TORRENT: &[u8] = b"d8:announce3:url4:infod6:lengthi42e4:name1:ae5:filesli1ei2ei3eee";
+
+    #[test]
+    fn flattens_document() {
+        let tape = Tape::parse(b"d1:ali1e2:bce1:bi-5ee").unwrap();
+        assert_eq!(&[
+            TapeNode { entry: TapeEntry::Dictionary { len: 2, next: 7 }, parent: None },
+            TapeNode { entry: TapeEntry::String { start: 3, end: 4 }, parent: Some(0) },
+            TapeNode { entry: TapeEntry::List { len: 2, next: 5 }, parent: Some(0) },
+            TapeNode { entry: TapeEntry::Integer(1), parent: Some(2) },
+            TapeNode { entry: TapeEntry::String { start: 10, end: 12 }, parent: Some(2) },
+            TapeNode { entry: TapeEntry::String { start: 15, end: 16 }, parent: Some(0) },
+            TapeNode { entry: TapeEntry::Integer(-5), parent: Some(0) },
+        ], tape.nodes());
+    }
+
+    #[test]
+    fn navigates_with_cursors() {
+        let tape = Tape::parse(TORRENT).unwrap();
+        let root = tape.root();
+
+        assert_eq!(Some(3), root.len());
+        assert_eq!(Some(b"url".as_ref()), root.get(b"announce").and_then(|c| c.as_bytes()));
+        let info = root.get(b"info").unwrap();
+        assert_eq!(Some(42), info.get(b"length").and_then(|c| c.as_int()));
+        assert_eq!(Some(0), info.parent().map(|c| c.position()));
+        assert_eq!(None, root.parent().map(|c| c.position()));
+
+        let files = root.get(b"files").unwrap();
+        assert_eq!(vec![Some(1), Some(2), Some(3)], files.children().map(|c| c.as_int()).collect::<Vec<_>>());
+        assert_eq!(Some(3), files.at(2).and_then(|c| c.as_int()));
+        assert_eq!(None, files.at(3).map(|c| c.position()));
+        assert_eq!(None, files.at(2).and_then(|c| c.next_sibling()).map(|c| c.position()));
+        assert_eq!(None, info.at(0).map(|c| c.position()));
+        assert_eq!(None, files.get(b"x").map(|c| c.position()));
+        assert_eq!(None, root.get(b"missing").map(|c| c.position()));
+    }
+
+    #[test]
+    fn empty_containers_have_no_children() {
+        let tape = Tape::parse(b"ldelee").unwrap();
+        let children: Vec<_> = tape.root().children().collect();
+        assert_eq!(2, children.len());
+        assert_eq!(Some(true), children[0].is_empty());
+        assert_eq!(None, children[0].first_child().map(|c| c.position()));
+        assert_eq!(None, children[1].first_child().map(|c| c.position()));
+        assert_eq!(None, tape.cursor_at(3).map(|c| c.position()));
+    }
+
+    #[test]
+    fn reports_decoding_errors() {
+        assert_eq!(Err(DecodingError::UnterminatedDictionary { start_offset: 0 }), Tape::parse(b"d1:ai1e"));
+        assert_eq!(Err(DecodingError::NegativeZero), Tape::parse(b"i-0e"));
+    }
+
+    #[test]
+    fn agrees_with_tree_decoder() {
+        let docs: [&[u8]; 6] = [
+            b"i-42e",
+            b"0:",
+            b"le",
+            TORRENT,
+            b"d5:innerd1:ai345e1:b3:wowe6:inner2dee",
+            b"llleelleei9e3:abcd1:xl1:ye1:zdeee",
+        ];
+        for doc in docs {
+            let value = crate::bdecode::decode(doc).unwrap();
+            assert_eq!(value, Tape::parse(doc).unwrap().to_value());
+            assert_eq!(value, value.to_tape().to_value());
+        }
+    }
+
+    #[test]
+    fn owned_tape_matches_parsed_structure() {
+        let parsed = Tape::parse(TORRENT).unwrap();
+        let owned = parsed.to_value().to_tape();
+
+        let entries = |tape: &Tape| tape.nodes().iter()
+            .map(|node| (node.parent, match node.entry {
+                TapeEntry::String { .. } => None,
+                entry => Some(entry),
+            }))
+            .collect::<Vec<_>>();
+        assert_eq!(entries(&parsed), entries(&owned));
+        assert_eq!(Some(b"url".as_ref()), owned.root().get(b"announce").and_then(|c| c.as_bytes()));
+        assert_eq!(Some(42), owned.root().get(b"info").and_then(|c| c.get(b"length")).and_then(|c| c.as_int()));
+    }
+}
@@ -0,0 +1,58 @@
+// Deterministic pseudo-random inputs for the fuzz style tests, so every run
+// and every decoder sees the same cases.
+
+pub const SEED: u64 = 0x2545f4914f6cdd1d;
+
+// Bytes that make up most of bencode, so random strings hit the grammar often
+pub const ALPHABET: &[u8] = b"ilde0123456789:-";
+
+// Valid document whose mutations exercise every kind of value
+pub const SAMPLE: &[u8] = b"d5:innerd1:ai-345e1:b3:wowe4:listli9223372036854775807e0:lee6:inner2dee";
+
+pub struct Xorshift(u64);
+
+impl Xorshift {
+    pub fn new(seed: u64) -> Xorshift {
+        Xorshift(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // Uniform enough in 0..n for test input
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    // Up to `max_len` bytes drawn from ALPHABET
+    pub fn random_doc(&mut self, max_len: usize) -> Vec<u8> {
+        let len = self.below(max_len + 1);
+        (0..len).map(|_| ALPHABET[self.below(ALPHABET.len())]).collect()
+    }
+
+    // A copy of `valid` with a few bytes replaced, then cut at a random point
+    pub fn mutate(&mut self, valid: &[u8]) -> Vec<u8> {
+        let mut doc = valid.to_vec();
+        for _ in 0..self.below(4) {
+            let i = self.below(doc.len());
+            doc[i] = match self.below(3) {
+                0 => ALPHABET[self.below(ALPHABET.len())],
+                1 => b'9',
+                _ => self.next_u64() as u8,
+            };
+        }
+        doc.truncate(self.below(doc.len() + 1));
+        doc
+    }
+}
+
+// `count` random documents of up to `max_len` bytes, then `count` mutations
+// of SAMPLE
+pub fn fuzz_inputs(count: usize, max_len: usize) -> impl Iterator<Item = Vec<u8>> {
+    let mut rng = Xorshift::new(SEED);
+    (0..2 * count).map(move |i| if i < count { rng.random_doc(max_len) } else { rng.mutate(SAMPLE) })
+}
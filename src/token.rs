@@ -0,0 +1,287 @@
+// Pull based parsing: BTokenizer walks a buffer and yields one token per
+// structural element instead of building a BEncodingType tree. Useful for
+// filtering or scanning huge documents while keeping memory flat.
+//
+// The grammar and the errors match decode, including errors inside
+// dictionary values being wrapped in InvalidValueForKey for each enclosing
+// key (limit errors excepted). Tokens stop after the first complete value.
+
+use crate::bdecode::{BDecoder, StrSpan, MAX_DEPTH};
+use crate::bytestring::ToByteString;
+use crate::error::DecodingError;
+
+type Result<T> = std::result::Result<T, DecodingError>;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TokenKind<'a> {
+    Int(i64),
+    // Length prefix of a string; its content follows as StrBytes
+    StrStart(usize),
+    StrBytes(&'a [u8]),
+    ListStart,
+    DictStart,
+    // Closes the innermost list or dictionary
+    End,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Token<'a> {
+    // Input offset the token starts at
+    pub offset: usize,
+    pub kind: TokenKind<'a>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Frame {
+    List { start: usize },
+    // `key` is set while the value for that key is being read
+    Dict { start: usize, key: Option<StrSpan> },
+}
+
+pub struct BTokenizer<'a> {
+    bytes: &'a [u8],
+    decoder: BDecoder<'a>,
+    stack: Vec<Frame>,
+    // Content of the string whose StrStart was just returned, and whether it
+    // is a dictionary key
+    pending: Option<(StrSpan, bool)>,
+    started: bool,
+    done: bool,
+}
+
+impl<'a> BTokenizer<'a> {
+    pub fn new(bytes: &'a [u8]) -> BTokenizer<'a> {
+        BTokenizer { bytes, decoder: BDecoder::new(bytes), stack: Vec::new(), pending: None, started: false, done: false }
+    }
+
+    // Number of containers currently open
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token<'a>>> {
+        if let Some((span, is_key)) = self.pending.take() {
+            if !is_key {
+                self.value_done();
+            }
+            return Ok(Some(Token { offset: span.content, kind: TokenKind::StrBytes(&self.bytes[span.content..span.end]) }));
+        }
+        let Some(&frame) = self.stack.last() else {
+            if self.started {
+                return Ok(None);
+            }
+            self.started = true;
+            return self.read_value().map(Some);
+        };
+
+        let offset = self.decoder.cursor();
+        match frame {
+            Frame::List { start } => match self.decoder.peek() {
+                Ok(b'e') => Ok(Some(self.close(offset))),
+                Ok(_) => self.read_value().map(Some),
+                Err(_) => Err(self.container_error(DecodingError::UnterminatedList { start_offset: start })),
+            },
+            Frame::Dict { start, key: None } => match self.decoder.peek() {
+                Ok(b'e') => Ok(Some(self.close(offset))),
+                Ok(_) => {
+                    let span = self.decoder.read_str_span().map_err(|err| self.container_error(err))?;
+                    if matches!(self.decoder.peek(), Ok(b'e') | Err(_)) {
+                        let key = self.span_bytes(span).to_byte_string();
+                        return Err(self.container_error(DecodingError::KeyWithoutValue(key)));
+                    }
+                    if let Some(Frame::Dict { key, .. }) = self.stack.last_mut() {
+                        *key = Some(span);
+                    }
+                    Ok(Some(self.str_start(span, true)))
+                }
+                Err(_) => Err(self.container_error(DecodingError::UnterminatedDictionary { start_offset: start })),
+            },
+            Frame::Dict { key: Some(_), .. } => self.read_value().map(Some),
+        }
+    }
+
+    // Reads the start of a value at the cursor
+    fn read_value(&mut self) -> Result<Token<'a>> {
+        let offset = self.decoder.cursor();
+        let token = match self.decoder.peek().map_err(|err| self.value_error(err))? {
+            b'i' => {
+                let int = self.decoder.parse_int().map_err(|err| self.value_error(err))?;
+                self.value_done();
+                Token { offset, kind: TokenKind::Int(int) }
+            }
+            chr @ (b'l' | b'd') => {
+                if self.stack.len() == MAX_DEPTH {
+                    return Err(self.value_error(DecodingError::NestingTooDeep(MAX_DEPTH)));
+                }
+                self.decoder.expect_char(chr).map_err(|err| self.value_error(err))?;
+                if chr == b'l' {
+                    self.stack.push(Frame::List { start: offset });
+                    Token { offset, kind: TokenKind::ListStart }
+                } else {
+                    self.stack.push(Frame::Dict { start: offset, key: None });
+                    Token { offset, kind: TokenKind::DictStart }
+                }
+            }
+            _ => {
+                let span = self.decoder.read_str_span().map_err(|err| self.value_error(err))?;
+                self.str_start(span, false)
+            }
+        };
+        Ok(token)
+    }
+
+    fn str_start(&mut self, span: StrSpan, is_key: bool) -> Token<'a> {
+        self.pending = Some((span, is_key));
+        Token { offset: span.start, kind: TokenKind::StrStart(span.end - span.content) }
+    }
+
+    fn close(&mut self, offset: usize) -> Token<'a> {
+        self.decoder.expect_char(b'e').expect("peeked 'e'");
+        self.stack.pop();
+        self.value_done();
+        Token { offset, kind: TokenKind::End }
+    }
+
+    // Marks the value being read in the innermost container as finished
+    fn value_done(&mut self) {
+        if let Some(Frame::Dict { key, .. }) = self.stack.last_mut() {
+            *key = None;
+        }
+    }
+
+    fn span_bytes(&self, span: StrSpan) -> &'a [u8] {
+        &self.bytes[span.content..span.end]
+    }
+
+    // Attributes an error in a value to the keys of all enclosing dictionaries
+    fn value_error(&self, err: DecodingError) -> DecodingError {
+        self.wrap(err, self.stack.len())
+    }
+
+    // Like value_error, for errors of the innermost container itself
+    fn container_error(&self, err: DecodingError) -> DecodingError {
+        self.wrap(err, self.stack.len().saturating_sub(1))
+    }
+
+    fn wrap(&self, err: DecodingError, frames: usize) -> DecodingError {
+        self.stack[..frames].iter().rev().fold(err, |err, frame| match frame {
+            Frame::Dict { key: Some(key), .. } => err.in_value_of(self.span_bytes(*key).to_byte_string()),
+            _ => err,
+        })
+    }
+}
+
+impl<'a> Iterator for BTokenizer<'a> {
+    type Item = Result<Token<'a>>;
+
+    // Yields None after the first complete value and after the first error
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.next_token();
+        self.done = !matches!(res, Ok(Some(_)));
+        res.transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::iter::Peekable;
+
+    use linked_hash_map::LinkedHashMap;
+
+    use crate::bdecode::{decode, BEncodingType};
+    use crate::bytestring::ByteString;
+    use crate::test_util::fuzz_inputs;
+
+    use super::*;
+
+    type Tokens<'a> = Peekable<BTokenizer<'a>>;
+
+    // Rebuilds the tree decode would produce from the token stream
+    fn build(tokens: &mut Tokens) -> Result<BEncodingType> {
+        match tokens.next().expect("token stream ended early")?.kind {
+            TokenKind::Int(i) => Ok(BEncodingType::Integer(i)),
+            TokenKind::StrStart(_) => match tokens.next().expect("missing string bytes")?.kind {
+                TokenKind::StrBytes(bytes) => Ok(BEncodingType::String(bytes.to_byte_string())),
+                other => panic!("expected string bytes, got {:?}", other),
+            },
+            TokenKind::ListStart => {
+                let mut list = Vec::new();
+                while !at_end(tokens) {
+                    list.push(build(tokens)?);
+                }
+                Ok(BEncodingType::List(list))
+            }
+            TokenKind::DictStart => {
+                let mut dict = LinkedHashMap::new();
+                while !at_end(tokens) {
+                    let BEncodingType::String(key) = build(tokens)? else { panic!("non-string key") };
+                    dict.insert(key, build(tokens)?);
+                }
+                Ok(BEncodingType::Dictionary(dict))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    // Consumes the End token closing a container, if it is next
+    fn at_end(tokens: &mut Tokens) -> bool {
+        tokens.next_if(|token| matches!(token, Ok(Token { kind: TokenKind::End, .. }))).is_some()
+    }
+
+    fn tree(bytes: &[u8]) -> Result<BEncodingType> {
+        build(&mut BTokenizer::new(bytes).peekable())
+    }
+
+    #[test]
+    fn yields_tokens_with_offsets() {
+        let tokens: Vec<_> = BTokenizer::new(b"d1:ali1ei-2ee1:bdee").collect::<Result<_>>().unwrap();
+        let expected = vec![
+            (0, TokenKind::DictStart),
+            (1, TokenKind::StrStart(1)),
+            (3, TokenKind::StrBytes(b"a")),
+            (4, TokenKind::ListStart),
+            (5, TokenKind::Int(1)),
+            (8, TokenKind::Int(-2)),
+            (12, TokenKind::End),
+            (13, TokenKind::StrStart(1)),
+            (15, TokenKind::StrBytes(b"b")),
+            (16, TokenKind::DictStart),
+            (17, TokenKind::End),
+            (18, TokenKind::End),
+        ];
+        assert_eq!(expected, tokens.iter().map(|t| (t.offset, t.kind)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stops_after_first_value_or_error() {
+        let mut tokens = BTokenizer::new(b"i1ei2e");
+        assert_eq!(Some(Ok(Token { offset: 0, kind: TokenKind::Int(1) })), tokens.next());
+        assert_eq!(None, tokens.next());
+
+        let mut tokens = BTokenizer::new(b"d1:ad1:bi1x");
+        assert_eq!(6, tokens.by_ref().take_while(Result::is_ok).count());
+        assert_eq!(None, tokens.next());
+    }
+
+    #[test]
+    fn agrees_with_tree_decoder() {
+        let docs: &[&[u8]] = &[
+            b"", b"i-0e", b"3:ab", b"l", b"li1e", b"d", b"d1:a", b"d1:ae", b"d1:ai1e", b"di1ei2ee",
+            b"d1:ad1:bli1eee", b"d1:ad1:bli1ee", b"d1:ad1:bl", b"d1:ad1:b1x", b"d1:ad1:bixee",
+            b"d5:innerd1:ai-345e1:b3:wowe4:listli9223372036854775807e0:lee6:inner2dee",
+        ];
+        for doc in docs {
+            assert_eq!(decode(doc), tree(doc), "{:?}", ByteString(doc.to_vec()));
+        }
+        let mut nested = vec![b'l'; MAX_DEPTH + 1];
+        nested.extend(vec![b'e'; MAX_DEPTH + 1]);
+        assert_eq!(decode(&nested), tree(&nested));
+
+        for doc in fuzz_inputs(20_000, 30) {
+            assert_eq!(decode(&doc), tree(&doc), "{:?}", ByteString(doc.clone()));
+        }
+    }
+}
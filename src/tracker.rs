@@ -0,0 +1,754 @@
+use std::net::{IpAddr, SocketAddr};
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::bdecode::{decode, BEncodingType};
+use crate::bencode::encode_sorted;
+use crate::bytestring::{ByteString, ToByteString};
+use crate::compact::{self, IpField};
+use crate::error::{TrackerError, UrlError};
+
+type Result<T> = std::result::Result<T, UrlError>;
+type TrackerResult<T> = std::result::Result<T, TrackerError>;
+
+const SCHEMES: [&str; 3] = ["http", "https", "udp"];
+
+// Split view of an announce url. Scheme and host are already lowercased.
+pub(crate) struct TrackerUrl<'a> {
+    pub(crate) scheme: String,
+    pub(crate) userinfo: Option<&'a str>,
+    // IPv6 addresses keep their brackets
+    pub(crate) host: String,
+    pub(crate) port: Option<u16>,
+    // Path, query and fragment
+    pub(crate) rest: &'a str,
+}
+
+impl<'a> TrackerUrl<'a> {
+    pub(crate) fn parse(url: &'a ByteString) -> Result<TrackerUrl<'a>> {
+        let s = std::str::from_utf8(&url.0).or(Err(UrlError::NotUtf8))?;
+        if let Some(chr) = s.chars().find(|c| c.is_ascii_control() || c.is_whitespace()) {
+            return Err(UrlError::InvalidCharacter(chr));
+        }
+
+        let (scheme, after) = s.split_once("://").ok_or(UrlError::MissingScheme)?;
+        let scheme = scheme.to_ascii_lowercase();
+        if !SCHEMES.contains(&scheme.as_str()) {
+            return Err(UrlError::UnsupportedScheme(scheme));
+        }
+
+        let authority_end = after.find(['/', '?', '#']).unwrap_or(after.len());
+        let (authority, rest) = after.split_at(authority_end);
+        let (userinfo, hostport) = match authority.rsplit_once('@') {
+            Some((userinfo, hostport)) => (Some(userinfo), hostport),
+            None => (None, authority),
+        };
+
+        // The port separator is the last ':' that is not inside an IPv6 literal
+        let port_sep = match hostport.rfind(']') {
+            Some(bracket) => hostport[bracket..].find(':').map(|i| bracket + i),
+            None => hostport.rfind(':'),
+        };
+        let (host, port) = match port_sep {
+            Some(i) => {
+                let port = &hostport[i + 1..];
+                // u16::from_str would also take a leading '+'
+                let invalid = || UrlError::InvalidPort(port.to_string());
+                if !port.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(invalid());
+                }
+                (&hostport[..i], Some(port.parse::<u16>().map_err(|_| invalid())?))
+            }
+            None => (hostport, None),
+        };
+        if host.is_empty() || host == "[]" {
+            return Err(UrlError::MissingHost);
+        }
+        // UDP trackers have no well known port to fall back on
+        if port.is_none() && scheme == "udp" {
+            return Err(UrlError::InvalidPort(String::new()));
+        }
+
+        check_percent_encoding(userinfo.unwrap_or(""))?;
+        check_percent_encoding(rest)?;
+
+        Ok(TrackerUrl { scheme, userinfo, host: host.to_ascii_lowercase(), port, rest })
+    }
+
+    pub(crate) fn default_port(&self) -> Option<u16> {
+        match self.scheme.as_str() {
+            "http" => Some(80),
+            "https" => Some(443),
+            _ => None,
+        }
+    }
+}
+
+fn check_percent_encoding(s: &str) -> Result<()> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let valid = bytes.get(i + 1..i + 3)
+                .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+            if !valid {
+                return Err(UrlError::InvalidPercentEncoding(s[i..].chars().take(3).collect()));
+            }
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    Ok(())
+}
+
+// Checks that `url` is an announce url a client could actually contact.
+pub fn validate_url(url: &ByteString) -> Result<()> {
+    TrackerUrl::parse(url).map(|_| ())
+}
+
+// Validates `url` and rewrites it into a canonical form: lowercased scheme and
+// host, and no explicit port when it is the default one for the scheme.
+pub fn normalize_url(url: &ByteString) -> Result<ByteString> {
+    let parsed = TrackerUrl::parse(url)?;
+    let mut out = format!("{}://", parsed.scheme);
+    if let Some(userinfo) = parsed.userinfo {
+        out.push_str(userinfo);
+        out.push('@');
+    }
+    out.push_str(&parsed.host);
+    if let Some(port) = parsed.port.filter(|&port| Some(port) != parsed.default_port()) {
+        out.push_str(&format!(":{}", port));
+    }
+    out.push_str(parsed.rest);
+    Ok(out.as_str().to_byte_string())
+}
+
+// Derives the scrape url of an announce url following the usual convention:
+// the last path segment must start with "announce", which is swapped for
+// "scrape" while the rest of the segment and the query are kept. UDP trackers
+// scrape on the announce endpoint itself. Returns None for invalid urls and
+// for trackers whose layout does not support scraping.
+pub fn scrape_url(announce: &ByteString) -> Option<ByteString> {
+    let parsed = TrackerUrl::parse(announce).ok()?;
+    if parsed.scheme == "udp" {
+        return Some(announce.clone());
+    }
+    let path_end = parsed.rest.find(['?', '#']).unwrap_or(parsed.rest.len());
+    let segment = parsed.rest[..path_end].rfind('/')? + 1;
+    if !parsed.rest[segment..].starts_with("announce") {
+        return None;
+    }
+
+    let url = &announce.0;
+    let at = url.len() - parsed.rest.len() + segment;
+    let mut out = url[..at].to_vec();
+    out.extend_from_slice(b"scrape");
+    out.extend_from_slice(&url[at + "announce".len()..]);
+    Some(ByteString(out))
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Peer {
+    pub peer_id: Option<ByteString>,
+    pub addr: PeerAddr,
+    // The `ip` field as sent, text or binary, for dictionary model peers with
+    // an IP address. Builders write it back the same way.
+    pub ip: Option<IpField>,
+}
+
+// BEP 3 lets the dictionary model give a DNS name instead of an IP address
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PeerAddr {
+    Ip(SocketAddr),
+    Host { host: String, port: u16 },
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        PeerAddr::Ip(addr)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AnnounceResponse {
+    pub interval: i64,
+    pub min_interval: Option<i64>,
+    pub tracker_id: Option<ByteString>,
+    pub complete: Option<i64>,
+    pub incomplete: Option<i64>,
+    pub peers: Vec<Peer>,
+    // A `warning message` sent alongside an otherwise successful response
+    pub warning: Option<ByteString>,
+    // The client's address as the tracker sees it
+    pub external_ip: Option<IpField>,
+}
+
+type Dict = LinkedHashMap<ByteString, BEncodingType>;
+
+// Parses the body of an HTTP announce response. A `failure reason` is reported
+// as TrackerError::Failure before anything else is looked at, so a failed
+// announce is never mistaken for an empty peer list.
+pub fn parse_announce_response(bytes: &[u8]) -> TrackerResult<AnnounceResponse> {
+    let dict = match decode(bytes)? {
+        BEncodingType::Dictionary(dict) => dict,
+        _ => return Err(TrackerError::NotADictionary),
+    };
+    if let Some(reason) = get_bytes(&dict, "failure reason")? {
+        return Err(TrackerError::Failure(reason.clone()));
+    }
+
+    // BEP 7 moves IPv6 peers to a separate compact `peers6`, which may be all
+    // there is for an IPv6-only swarm
+    let peers6 = match get_bytes(&dict, "peers6")? {
+        Some(compact) => compact::peers_v6(&compact.0).ok_or(TrackerError::InvalidField("peers6"))?,
+        None => Vec::new(),
+    };
+    let mut peers = match dict.get(&"peers".to_byte_string()) {
+        Some(BEncodingType::String(compact)) => parse_compact_peers(compact)?,
+        Some(BEncodingType::List(list)) => list.iter().map(parse_peer_dict).collect::<TrackerResult<_>>()?,
+        Some(_) => return Err(TrackerError::InvalidField("peers")),
+        None if dict.contains_key(&"peers6".to_byte_string()) => Vec::new(),
+        None => return Err(TrackerError::MissingField("peers")),
+    };
+    peers.extend(peers6.into_iter().map(|addr| Peer { peer_id: None, addr: SocketAddr::V6(addr).into(), ip: None }));
+    let external_ip = match get_bytes(&dict, "external ip")? {
+        Some(ip) => Some(IpField::parse(&ip.0).ok_or(TrackerError::InvalidField("external ip"))?),
+        None => None,
+    };
+
+    Ok(AnnounceResponse {
+        interval: get_int(&dict, "interval")?.ok_or(TrackerError::MissingField("interval"))?,
+        min_interval: get_int(&dict, "min interval")?,
+        tracker_id: get_bytes(&dict, "tracker id")?.cloned(),
+        complete: get_int(&dict, "complete")?,
+        incomplete: get_int(&dict, "incomplete")?,
+        peers,
+        warning: get_bytes(&dict, "warning message")?.cloned(),
+        external_ip,
+    })
+}
+
+fn parse_compact_peers(compact: &ByteString) -> TrackerResult<Vec<Peer>> {
+    let peers = compact::peers_v4(&compact.0).ok_or(TrackerError::InvalidField("peers"))?;
+    Ok(peers.into_iter().map(|addr| Peer { peer_id: None, addr: SocketAddr::V4(addr).into(), ip: None }).collect())
+}
+
+fn parse_peer_dict(peer: &BEncodingType) -> TrackerResult<Peer> {
+    let dict = match peer {
+        BEncodingType::Dictionary(dict) => dict,
+        _ => return Err(TrackerError::InvalidField("peers")),
+    };
+    let ip = get_bytes(dict, "ip")?.ok_or(TrackerError::MissingField("ip"))?;
+    let port = get_int(dict, "port")?.ok_or(TrackerError::MissingField("port"))?;
+    let port = u16::try_from(port).or(Err(TrackerError::InvalidField("port")))?;
+    // The field is meant to be text, so a name is preferred over reading 4 or
+    // 16 bytes as a binary address
+    let (addr, ip) = match (std::str::from_utf8(&ip.0), IpField::parse(&ip.0)) {
+        (Ok(host), _) if is_hostname(host) && host.parse::<IpAddr>().is_err() =>
+            (PeerAddr::Host { host: host.to_string(), port }, None),
+        (_, Some(ip)) => (PeerAddr::Ip(SocketAddr::new(ip.addr, port)), Some(ip)),
+        _ => return Err(TrackerError::InvalidField("ip")),
+    };
+    Ok(Peer { peer_id: get_bytes(dict, "peer id")?.cloned(), addr, ip })
+}
+
+// Dot separated labels of letters, digits and hyphens, with an optional
+// trailing dot
+fn is_hostname(host: &str) -> bool {
+    let labels = host.strip_suffix('.').unwrap_or(host);
+    !labels.is_empty() && labels.len() <= 253 && labels.split('.').all(|label| {
+        !label.is_empty() && label.len() <= 63 && !label.starts_with('-') && !label.ends_with('-')
+            && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    })
+}
+
+fn get_int(dict: &Dict, key: &'static str) -> TrackerResult<Option<i64>> {
+    match dict.get(&key.to_byte_string()) {
+        Some(BEncodingType::Integer(i)) => Ok(Some(*i)),
+        Some(_) => Err(TrackerError::InvalidField(key)),
+        None => Ok(None),
+    }
+}
+
+fn get_bytes<'a>(dict: &'a Dict, key: &'static str) -> TrackerResult<Option<&'a ByteString>> {
+    match dict.get(&key.to_byte_string()) {
+        Some(BEncodingType::String(bs)) => Ok(Some(bs)),
+        Some(_) => Err(TrackerError::InvalidField(key)),
+        None => Ok(None),
+    }
+}
+
+// Builds the body of a successful announce response. Peers are written in the
+// compact form by default, IPv4 ones to `peers` and IPv6 ones to `peers6`.
+// Peers given by hostname have no compact form and are only written in the
+// dictionary model.
+pub struct AnnounceResponseBuilder {
+    interval: i64,
+    min_interval: Option<i64>,
+    tracker_id: Option<ByteString>,
+    complete: Option<i64>,
+    incomplete: Option<i64>,
+    warning: Option<ByteString>,
+    external_ip: Option<IpField>,
+    peers: Vec<Peer>,
+    compact: bool,
+}
+
+impl AnnounceResponseBuilder {
+    pub fn new(interval: i64) -> AnnounceResponseBuilder {
+        AnnounceResponseBuilder {
+            interval,
+            min_interval: None,
+            tracker_id: None,
+            complete: None,
+            incomplete: None,
+            warning: None,
+            external_ip: None,
+            peers: Vec::new(),
+            compact: true,
+        }
+    }
+
+    pub fn min_interval(mut self, min_interval: i64) -> Self {
+        self.min_interval = Some(min_interval);
+        self
+    }
+
+    pub fn tracker_id(mut self, tracker_id: ByteString) -> Self {
+        self.tracker_id = Some(tracker_id);
+        self
+    }
+
+    pub fn complete(mut self, complete: i64) -> Self {
+        self.complete = Some(complete);
+        self
+    }
+
+    pub fn incomplete(mut self, incomplete: i64) -> Self {
+        self.incomplete = Some(incomplete);
+        self
+    }
+
+    pub fn warning(mut self, warning: ByteString) -> Self {
+        self.warning = Some(warning);
+        self
+    }
+
+    pub fn external_ip(mut self, external_ip: IpField) -> Self {
+        self.external_ip = Some(external_ip);
+        self
+    }
+
+    pub fn peers(mut self, peers: Vec<SocketAddr>) -> Self {
+        self.peers = peers.into_iter().map(|addr| Peer { peer_id: None, addr: addr.into(), ip: None }).collect();
+        self
+    }
+
+    // Adds a peer as parsed, keeping its `peer id` and `ip` representation in
+    // the dictionary model
+    pub fn peer(mut self, peer: Peer) -> Self {
+        self.peers.push(peer);
+        self
+    }
+
+    // Switches to the dictionary peer list (`ip` and `port` per peer)
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        let mut dict = Dict::new();
+        insert(&mut dict, "interval", BEncodingType::Integer(self.interval));
+        let optional_ints = [
+            ("min interval", self.min_interval),
+            ("complete", self.complete),
+            ("incomplete", self.incomplete),
+        ];
+        for (key, value) in optional_ints {
+            if let Some(value) = value {
+                insert(&mut dict, key, BEncodingType::Integer(value));
+            }
+        }
+        if let Some(tracker_id) = self.tracker_id {
+            insert(&mut dict, "tracker id", BEncodingType::String(tracker_id));
+        }
+        if let Some(warning) = self.warning {
+            insert(&mut dict, "warning message", BEncodingType::String(warning));
+        }
+        if let Some(external_ip) = self.external_ip {
+            insert(&mut dict, "external ip", BEncodingType::String(ByteString(external_ip.to_bytes())));
+        }
+
+        if self.compact {
+            let (mut peers, mut peers6) = (Vec::new(), Vec::new());
+            for peer in &self.peers {
+                match &peer.addr {
+                    PeerAddr::Ip(SocketAddr::V4(addr)) => compact::write_peer_v4(&mut peers, addr),
+                    PeerAddr::Ip(SocketAddr::V6(addr)) => compact::write_peer_v6(&mut peers6, addr),
+                    PeerAddr::Host { .. } => {}
+                }
+            }
+            insert(&mut dict, "peers", BEncodingType::String(ByteString(peers)));
+            if !peers6.is_empty() {
+                insert(&mut dict, "peers6", BEncodingType::String(ByteString(peers6)));
+            }
+        } else {
+            let peers = self.peers.into_iter()
+                .map(|peer| {
+                    let (ip, port) = match peer.addr {
+                        // The recorded form only applies while it still matches the address
+                        PeerAddr::Ip(addr) => match peer.ip.filter(|ip| ip.addr == addr.ip()) {
+                            Some(ip) => (ip.to_bytes(), addr.port()),
+                            None => (addr.ip().to_string().into_bytes(), addr.port()),
+                        },
+                        PeerAddr::Host { host, port } => (host.into_bytes(), port),
+                    };
+                    let mut peer_dict = Dict::new();
+                    insert(&mut peer_dict, "ip", BEncodingType::String(ByteString(ip)));
+                    if let Some(peer_id) = peer.peer_id {
+                        insert(&mut peer_dict, "peer id", BEncodingType::String(peer_id));
+                    }
+                    insert(&mut peer_dict, "port", BEncodingType::Integer(port as i64));
+                    BEncodingType::Dictionary(peer_dict)
+                })
+                .collect();
+            insert(&mut dict, "peers", BEncodingType::List(peers));
+        }
+        encode_sorted(BEncodingType::Dictionary(dict))
+    }
+}
+
+// Body of an announce or scrape response rejecting the request
+pub fn failure_response(reason: &str) -> Vec<u8> {
+    let mut dict = Dict::new();
+    insert(&mut dict, "failure reason", BEncodingType::String(reason.to_byte_string()));
+    encode_sorted(BEncodingType::Dictionary(dict))
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ScrapeStats {
+    pub complete: i64,
+    pub downloaded: i64,
+    pub incomplete: i64,
+}
+
+// Builds the body of a scrape response, one entry per info hash
+#[derive(Default)]
+pub struct ScrapeResponseBuilder {
+    files: Vec<([u8; 20], ScrapeStats)>,
+    min_request_interval: Option<i64>,
+}
+
+impl ScrapeResponseBuilder {
+    pub fn new() -> ScrapeResponseBuilder {
+        ScrapeResponseBuilder::default()
+    }
+
+    pub fn file(mut self, info_hash: [u8; 20], stats: ScrapeStats) -> Self {
+        self.files.push((info_hash, stats));
+        self
+    }
+
+    pub fn min_request_interval(mut self, interval: i64) -> Self {
+        self.min_request_interval = Some(interval);
+        self
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        let mut files = Dict::new();
+        for (info_hash, stats) in self.files {
+            let mut file = Dict::new();
+            insert(&mut file, "complete", BEncodingType::Integer(stats.complete));
+            insert(&mut file, "downloaded", BEncodingType::Integer(stats.downloaded));
+            insert(&mut file, "incomplete", BEncodingType::Integer(stats.incomplete));
+            files.insert(ByteString(info_hash.to_vec()), BEncodingType::Dictionary(file));
+        }
+        let mut dict = Dict::new();
+        insert(&mut dict, "files", BEncodingType::Dictionary(files));
+        if let Some(interval) = self.min_request_interval {
+            let mut flags = Dict::new();
+            insert(&mut flags, "min_request_interval", BEncodingType::Integer(interval));
+            insert(&mut dict, "flags", BEncodingType::Dictionary(flags));
+        }
+        encode_sorted(BEncodingType::Dictionary(dict))
+    }
+}
+
+// Parses the body of a scrape response into the stats for each info hash, in
+// the order the tracker listed them
+pub fn parse_scrape_response(bytes: &[u8]) -> TrackerResult<Vec<([u8; 20], ScrapeStats)>> {
+    let dict = match decode(bytes)? {
+        BEncodingType::Dictionary(dict) => dict,
+        _ => return Err(TrackerError::NotADictionary),
+    };
+    if let Some(reason) = get_bytes(&dict, "failure reason")? {
+        return Err(TrackerError::Failure(reason.clone()));
+    }
+    let files = match dict.get(&"files".to_byte_string()) {
+        Some(BEncodingType::Dictionary(files)) => files,
+        Some(_) => return Err(TrackerError::InvalidField("files")),
+        None => return Err(TrackerError::MissingField("files")),
+    };
+    files.iter()
+        .map(|(info_hash, file)| {
+            let info_hash = info_hash.0.as_slice().try_into().or(Err(TrackerError::InvalidField("files")))?;
+            let BEncodingType::Dictionary(file) = file else {
+                return Err(TrackerError::InvalidField("files"));
+            };
+            let stat = |key| get_int(file, key)?.ok_or(TrackerError::MissingField(key));
+            let stats = ScrapeStats { complete: stat("complete")?, downloaded: stat("downloaded")?, incomplete: stat("incomplete")? };
+            Ok((info_hash, stats))
+        })
+        .collect()
+}
+
+fn insert(dict: &mut Dict, key: &str, value: BEncodingType) {
+    dict.insert(key.to_byte_string(), value);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compact::IpRepr;
+    use crate::error::DecodingError;
+
+    use super::*;
+
+    fn ip(addr: &str) -> PeerAddr {
+        PeerAddr::Ip(addr.parse().unwrap())
+    }
+
+    fn field(addr: &str, repr: IpRepr) -> Option<IpField> {
+        Some(IpField { addr: addr.parse().unwrap(), repr })
+    }
+
+    fn normalize(url: &str) -> Result<String> {
+        normalize_url(&url.to_byte_string()).map(|bs| bs.to_string())
+    }
+
+    #[test]
+    fn validate_accepts_common_trackers() {
+        assert_eq!(Ok(()), validate_url(&"http://tracker.example.org/announce".to_byte_string()));
+        assert_eq!(Ok(()), validate_url(&"https://t.example.org:8443/a/b?passkey=abc%20d".to_byte_string()));
+        assert_eq!(Ok(()), validate_url(&"udp://tracker.example.org:6969".to_byte_string()));
+        assert_eq!(Ok(()), validate_url(&"udp://[2001:db8::1]:6969/announce".to_byte_string()));
+    }
+
+    #[test]
+    fn validate_rejects_malformed_urls() {
+        let validate = |url: &str| validate_url(&url.to_byte_string());
+
+        assert_eq!(Err(UrlError::NotUtf8), validate_url(&ByteString(b"http://\xff/announce".to_vec())));
+        assert_eq!(Err(UrlError::MissingScheme), validate("tracker.example.org/announce"));
+        assert_eq!(Err(UrlError::UnsupportedScheme("ftp".to_string())), validate("ftp://example.org/"));
+        assert_eq!(Err(UrlError::MissingHost), validate("http:///announce"));
+        assert_eq!(Err(UrlError::MissingHost), validate("http://:80/announce"));
+        assert_eq!(Err(UrlError::InvalidPort("".to_string())), validate("http://example.org:/announce"));
+        assert_eq!(Err(UrlError::InvalidPort("99999".to_string())), validate("http://example.org:99999/"));
+        assert_eq!(Err(UrlError::InvalidPort("+80".to_string())), validate("http://example.org:+80/"));
+        assert_eq!(Err(UrlError::InvalidPort("".to_string())), validate("udp://example.org/announce"));
+        assert_eq!(Err(UrlError::InvalidPercentEncoding("%zz".to_string())), validate("http://example.org/%zz"));
+        assert_eq!(Err(UrlError::InvalidPercentEncoding("%4".to_string())), validate("http://example.org/?a=%4"));
+        assert_eq!(Err(UrlError::InvalidCharacter(' ')), validate("http://example.org/announce "));
+    }
+
+    #[test]
+    fn normalize_lowercases_and_strips_default_ports() {
+        assert_eq!(Ok("http://tracker.example.org/Announce".to_string()), normalize("HTTP://Tracker.Example.org:80/Announce"));
+        assert_eq!(Ok("https://example.org?a=B".to_string()), normalize("https://example.org:443?a=B"));
+        assert_eq!(Ok("http://example.org:8080/announce".to_string()), normalize("http://example.org:8080/announce"));
+        assert_eq!(Ok("udp://example.org:80".to_string()), normalize("udp://EXAMPLE.org:80"));
+        assert_eq!(Ok("http://User@[2001:db8::1]/".to_string()), normalize("http://User@[2001:DB8::1]:80/"));
+    }
+
+    #[test]
+    fn scrape_url_convention() {
+        let scrape = |url: &str| scrape_url(&url.to_byte_string()).map(|bs| bs.to_string());
+
+        assert_eq!(Some("http://example.org/scrape".to_string()), scrape("http://example.org/announce"));
+        assert_eq!(Some("http://example.org/x/scrape.php".to_string()), scrape("http://example.org/x/announce.php"));
+        assert_eq!(Some("https://example.org/scrape?passkey=a/announce".to_string()),
+                   scrape("https://example.org/announce?passkey=a/announce"));
+        assert_eq!(Some("http://example.org:8080/a/scrape#x".to_string()), scrape("http://example.org:8080/a/announce#x"));
+        assert_eq!(Some("udp://example.org:6969".to_string()), scrape("udp://example.org:6969"));
+        assert_eq!(None, scrape("http://example.org/a"));
+        assert_eq!(None, scrape("http://example.org/announce/x"));
+        assert_eq!(None, scrape("http://example.org/myannounce"));
+        assert_eq!(None, scrape("http://example.org?announce"));
+        assert_eq!(None, scrape("ftp://example.org/announce"));
+    }
+
+    #[test]
+    fn announce_response_failure_reason() {
+        assert_eq!(Err(TrackerError::Failure("unregistered torrent".to_byte_string())),
+                   parse_announce_response(b"d14:failure reason20:unregistered torrente"));
+        // A failure wins even when the rest of the dict looks like a normal response
+        assert_eq!(Err(TrackerError::Failure("bad".to_byte_string())),
+                   parse_announce_response(b"d14:failure reason3:bad8:intervali1800e5:peers0:e"));
+    }
+
+    #[test]
+    fn announce_response_compact_peers() {
+        let resp = parse_announce_response(
+            b"d8:completei5e10:incompletei2e8:intervali1800e12:min intervali60e5:peers12:\x7f\x00\x00\x01\x1a\xe1\x0a\x00\x00\x02\x00\x5015:warning message4:slowe"
+        ).unwrap();
+
+        assert_eq!(1800, resp.interval);
+        assert_eq!(Some(60), resp.min_interval);
+        assert_eq!(Some(5), resp.complete);
+        assert_eq!(Some(2), resp.incomplete);
+        assert_eq!(Some("slow".to_byte_string()), resp.warning);
+        assert_eq!(vec![
+            Peer { peer_id: None, addr: ip("127.0.0.1:6881"), ip: None },
+            Peer { peer_id: None, addr: ip("10.0.0.2:80"), ip: None },
+        ], resp.peers);
+    }
+
+    #[test]
+    fn announce_response_dict_peers() {
+        let resp = parse_announce_response(
+            b"d8:intervali900e5:peersld2:ip9:127.0.0.17:peer id3:abc4:porti6881eed2:ip3:::14:porti1eed2:ip4:\x0a\x00\x00\x024:porti2eeee"
+        ).unwrap();
+
+        assert_eq!(vec![
+            Peer { peer_id: Some("abc".to_byte_string()), addr: ip("127.0.0.1:6881"), ip: field("127.0.0.1", IpRepr::Text) },
+            Peer { peer_id: None, addr: ip("[::1]:1"), ip: field("::1", IpRepr::Text) },
+            Peer { peer_id: None, addr: ip("10.0.0.2:2"), ip: field("10.0.0.2", IpRepr::Binary) },
+        ], resp.peers);
+    }
+
+    #[test]
+    fn announce_response_hostname_peers() {
+        let resp = parse_announce_response(
+            b"d8:intervali900e5:peersld2:ip16:peer.example.org4:porti6881eed2:ip9:127.0.0.14:porti1eeee"
+        ).unwrap();
+
+        assert_eq!(vec![
+            Peer { peer_id: None, addr: PeerAddr::Host { host: "peer.example.org".to_string(), port: 6881 }, ip: None },
+            Peer { peer_id: None, addr: ip("127.0.0.1:1"), ip: field("127.0.0.1", IpRepr::Text) },
+        ], resp.peers);
+        assert!(is_hostname("localhost."));
+        assert!(!is_hostname("-a.b"));
+        assert!(!is_hostname("a..b"));
+        assert!(!is_hostname(""));
+    }
+
+    #[test]
+    fn announce_response_ipv6_peers() {
+        let mut body = b"d8:intervali60e6:peers618:".to_vec();
+        body.extend(std::net::Ipv6Addr::LOCALHOST.octets());
+        body.extend(b"\x1a\xe1e");
+        let resp = parse_announce_response(&body).unwrap();
+        assert_eq!(vec![Peer { peer_id: None, addr: ip("[::1]:6881"), ip: None }], resp.peers);
+
+        assert_eq!(Err(TrackerError::InvalidField("peers6")), parse_announce_response(b"d8:intervali1e5:peers0:6:peers66:abcdefe"));
+        assert_eq!(Err(TrackerError::InvalidField("peers6")), parse_announce_response(b"d8:intervali1e5:peers0:6:peers6lee"));
+    }
+
+    #[test]
+    fn announce_response_invalid() {
+        assert_eq!(Err(TrackerError::Decoding(DecodingError::UnterminatedDictionary { start_offset: 0 })),
+                   parse_announce_response(b"d8:intervali1e"));
+        assert_eq!(Err(TrackerError::NotADictionary), parse_announce_response(b"le"));
+        assert_eq!(Err(TrackerError::MissingField("peers")), parse_announce_response(b"d8:intervali1ee"));
+        assert_eq!(Err(TrackerError::MissingField("interval")), parse_announce_response(b"d5:peers0:e"));
+        assert_eq!(Err(TrackerError::InvalidField("peers")), parse_announce_response(b"d8:intervali1e5:peers5:abcdee"));
+        assert_eq!(Err(TrackerError::InvalidField("interval")), parse_announce_response(b"d8:interval1:15:peers0:e"));
+        assert_eq!(Err(TrackerError::InvalidField("ip")), parse_announce_response(b"d8:intervali1e5:peersld2:ip8:bad host4:porti1eeee"));
+        assert_eq!(Err(TrackerError::InvalidField("port")), parse_announce_response(b"d8:intervali1e5:peersld2:ip7:1.2.3.44:porti70000eeee"));
+    }
+
+    #[test]
+    fn announce_builder_compact() {
+        let peers = vec!["127.0.0.1:6881".parse().unwrap(), "[::1]:80".parse().unwrap(), "10.0.0.2:80".parse().unwrap()];
+        let body = AnnounceResponseBuilder::new(1800)
+            .min_interval(60)
+            .complete(3)
+            .peers(peers)
+            .build();
+
+        let mut expected = b"d8:completei3e8:intervali1800e12:min intervali60e5:peers12:\x7f\x00\x00\x01\x1a\xe1\x0a\x00\x00\x02\x00\x506:peers618:".to_vec();
+        expected.extend(std::net::Ipv6Addr::LOCALHOST.octets());
+        expected.extend(b"\x00\x50e");
+        assert_eq!(expected, body);
+
+        let parsed = parse_announce_response(&body).unwrap();
+        let addrs: Vec<PeerAddr> = parsed.peers.into_iter().map(|peer| peer.addr).collect();
+        assert_eq!(vec![ip("127.0.0.1:6881"), ip("10.0.0.2:80"), ip("[::1]:80")], addrs);
+        assert_eq!(1800, parsed.interval);
+        assert_eq!(Some(60), parsed.min_interval);
+        assert_eq!(Some(3), parsed.complete);
+        assert_eq!(None, parsed.incomplete);
+    }
+
+    #[test]
+    fn announce_builder_dict_peers_round_trip() {
+        let peers: Vec<SocketAddr> = vec!["127.0.0.1:6881".parse().unwrap(), "[::1]:80".parse().unwrap()];
+        let body = AnnounceResponseBuilder::new(900)
+            .compact(false)
+            .warning("slow down".to_byte_string())
+            .tracker_id("xyz".to_byte_string())
+            .peers(peers.clone())
+            .build();
+
+        assert_eq!(b"d8:intervali900e5:peersld2:ip9:127.0.0.14:porti6881eed2:ip3:::14:porti80eee10:tracker id3:xyz15:warning message9:slow downe".to_vec(), body);
+        let parsed = parse_announce_response(&body).unwrap();
+        assert_eq!(peers.into_iter().map(PeerAddr::Ip).collect::<Vec<_>>(),
+                   parsed.peers.into_iter().map(|peer| peer.addr).collect::<Vec<_>>());
+        assert_eq!(Some("slow down".to_byte_string()), parsed.warning);
+        assert_eq!(Some("xyz".to_byte_string()), parsed.tracker_id);
+    }
+
+    #[test]
+    fn announce_builder_keeps_ip_representation() {
+        let body = b"d11:external ip4:\xc0\x00\x02\x018:intervali900e5:peersld2:ip4:\x0a\x00\x00\x027:peer id3:abc4:porti2eed2:ip16:peer.example.org4:porti3eed2:ip9:127.0.0.14:porti1eeee";
+        let parsed = parse_announce_response(body).unwrap();
+        assert_eq!(field("192.0.2.1", IpRepr::Binary), parsed.external_ip);
+        assert_eq!(field("10.0.0.2", IpRepr::Binary), parsed.peers[0].ip);
+
+        let rebuilt = parsed.peers.iter().cloned()
+            .fold(AnnounceResponseBuilder::new(900).compact(false), AnnounceResponseBuilder::peer)
+            .external_ip(parsed.external_ip.unwrap())
+            .build();
+        assert_eq!(body.to_vec(), rebuilt);
+        assert_eq!(Ok(parsed), parse_announce_response(&rebuilt));
+
+        assert_eq!(Err(TrackerError::InvalidField("external ip")),
+                   parse_announce_response(b"d11:external ip3:abc8:intervali1e5:peers0:e"));
+    }
+
+    #[test]
+    fn failure_response_round_trip() {
+        let body = failure_response("torrent not registered");
+        assert_eq!(b"d14:failure reason22:torrent not registerede".to_vec(), body);
+        assert_eq!(Err(TrackerError::Failure("torrent not registered".to_byte_string())), parse_announce_response(&body));
+    }
+
+    #[test]
+    fn scrape_builder() {
+        assert_eq!(b"d5:filesdee".to_vec(), ScrapeResponseBuilder::new().build());
+
+        let body = ScrapeResponseBuilder::new()
+            .file([b'b'; 20], ScrapeStats { complete: 1, downloaded: 2, incomplete: 3 })
+            .file([b'a'; 20], ScrapeStats { complete: 4, downloaded: 5, incomplete: 6 })
+            .min_request_interval(3600)
+            .build();
+        assert_eq!(b"d5:filesd20:aaaaaaaaaaaaaaaaaaaad8:completei4e10:downloadedi5e10:incompletei6ee\
+20:bbbbbbbbbbbbbbbbbbbbd8:completei1e10:downloadedi2e10:incompletei3eee\
+5:flagsd20:min_request_intervali3600eee".to_vec(), body);
+    }
+
+    #[test]
+    fn scrape_response_round_trip() {
+        let stats = ScrapeStats { complete: 1, downloaded: 2, incomplete: 3 };
+        let body = ScrapeResponseBuilder::new().file([b'a'; 20], stats).min_request_interval(60).build();
+        assert_eq!(Ok(vec![([b'a'; 20], stats)]), parse_scrape_response(&body));
+
+        assert_eq!(Err(TrackerError::Failure("no".to_byte_string())), parse_scrape_response(b"d14:failure reason2:noe"));
+        assert_eq!(Err(TrackerError::MissingField("files")), parse_scrape_response(b"de"));
+        assert_eq!(Err(TrackerError::InvalidField("files")), parse_scrape_response(b"d5:filesd3:abcdeee"));
+        assert_eq!(Err(TrackerError::MissingField("downloaded")),
+                   parse_scrape_response(b"d5:filesd20:aaaaaaaaaaaaaaaaaaaad8:completei1e10:incompletei3eeee"));
+    }
+}
@@ -0,0 +1,232 @@
+// Callback based (SAX style) decoding on top of BTokenizer. The visitor sees
+// every value as it is parsed and no tree is ever built, so picking a couple
+// of keys out of a huge document costs no more memory than its nesting.
+//
+// Any callback can return ControlFlow::Break to stop decoding early, in which
+// case the rest of the input is never looked at, let alone validated. The
+// returned Flow tells such an early stop apart from a complete visit.
+
+use std::ops::ControlFlow;
+
+use crate::error::DecodingError;
+use crate::token::{BTokenizer, TokenKind};
+
+type Result<T> = std::result::Result<T, DecodingError>;
+
+pub type Flow = ControlFlow<()>;
+
+// All callbacks default to doing nothing, so visitors only implement the ones
+// they are interested in
+pub trait BVisitor<'a> {
+    fn on_int(&mut self, _value: i64) -> Flow {
+        Flow::Continue(())
+    }
+
+    // A string value. Dictionary keys go to on_dict_key instead.
+    fn on_string(&mut self, _value: &'a [u8]) -> Flow {
+        Flow::Continue(())
+    }
+
+    // Called before the value for `key` is visited
+    fn on_dict_key(&mut self, _key: &'a [u8]) -> Flow {
+        Flow::Continue(())
+    }
+
+    fn on_list_start(&mut self) -> Flow {
+        Flow::Continue(())
+    }
+
+    fn on_list_end(&mut self) -> Flow {
+        Flow::Continue(())
+    }
+
+    fn on_dict_start(&mut self) -> Flow {
+        Flow::Continue(())
+    }
+
+    fn on_dict_end(&mut self) -> Flow {
+        Flow::Continue(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Open {
+    List,
+    Dict { expect_key: bool },
+}
+
+// Feeds the first value in `inp` to `visitor`. Returns Break if a callback
+// stopped the visit, and Continue once the whole value has been visited.
+// Errors are the same decode would report, and are returned once the
+// callbacks for everything before the error have run.
+pub fn decode_with_visitor<'a, V: BVisitor<'a> + ?Sized>(inp: &'a [u8], visitor: &mut V) -> Result<Flow> {
+    let mut open = Vec::new();
+    for token in BTokenizer::new(inp) {
+        let flow = match token?.kind {
+            TokenKind::Int(value) => {
+                value_done(&mut open);
+                visitor.on_int(value)
+            }
+            TokenKind::StrStart(_) => continue,
+            TokenKind::StrBytes(bytes) => match open.last_mut() {
+                Some(Open::Dict { expect_key: expect_key @ true }) => {
+                    *expect_key = false;
+                    visitor.on_dict_key(bytes)
+                }
+                _ => {
+                    value_done(&mut open);
+                    visitor.on_string(bytes)
+                }
+            },
+            TokenKind::ListStart => {
+                open.push(Open::List);
+                visitor.on_list_start()
+            }
+            TokenKind::DictStart => {
+                open.push(Open::Dict { expect_key: true });
+                visitor.on_dict_start()
+            }
+            TokenKind::End => {
+                let closed = open.pop();
+                value_done(&mut open);
+                match closed {
+                    Some(Open::Dict { .. }) => visitor.on_dict_end(),
+                    _ => visitor.on_list_end(),
+                }
+            }
+        };
+        if flow.is_break() {
+            return Ok(flow);
+        }
+    }
+    Ok(Flow::Continue(()))
+}
+
+// After a value inside a dictionary, the next string is a key again
+fn value_done(open: &mut [Open]) {
+    if let Some(Open::Dict { expect_key }) = open.last_mut() {
+        *expect_key = true;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bytestring::ToByteString;
+
+    use super::*;
+
+    // Records every callback as a line of text
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+    }
+
+    impl<'a> BVisitor<'a> for Recorder {
+        fn on_int(&mut self, value: i64) -> Flow {
+            self.events.push(format!("int {}", value));
+            Flow::Continue(())
+        }
+
+        fn on_string(&mut self, value: &'a [u8]) -> Flow {
+            self.events.push(format!("str {}", value.to_byte_string()));
+            Flow::Continue(())
+        }
+
+        fn on_dict_key(&mut self, key: &'a [u8]) -> Flow {
+            self.events.push(format!("key {}", key.to_byte_string()));
+            Flow::Continue(())
+        }
+
+        fn on_list_start(&mut self) -> Flow {
+            self.events.push("[".to_string());
+            Flow::Continue(())
+        }
+
+        fn on_list_end(&mut self) -> Flow {
+            self.events.push("]".to_string());
+            Flow::Continue(())
+        }
+
+        fn on_dict_start(&mut self) -> Flow {
+            self.events.push("{".to_string());
+            Flow::Continue(())
+        }
+
+        fn on_dict_end(&mut self) -> Flow {
+            self.events.push("}".to_string());
+            Flow::Continue(())
+        }
+    }
+
+    // Grabs the top level `announce` key and stops
+    #[derive(Default)]
+    struct Announce<'a> {
+        depth: usize,
+        take_next: bool,
+        announce: Option<&'a [u8]>,
+    }
+
+    impl<'a> BVisitor<'a> for Announce<'a> {
+        fn on_string(&mut self, value: &'a [u8]) -> Flow {
+            if self.take_next {
+                self.announce = Some(value);
+                return Flow::Break(());
+            }
+            Flow::Continue(())
+        }
+
+        fn on_dict_key(&mut self, key: &'a [u8]) -> Flow {
+            self.take_next = self.depth == 1 && key == b"announce";
+            Flow::Continue(())
+        }
+
+        fn on_dict_start(&mut self) -> Flow {
+            self.depth += 1;
+            Flow::Continue(())
+        }
+
+        fn on_dict_end(&mut self) -> Flow {
+            self.depth -= 1;
+            Flow::Continue(())
+        }
+    }
+
+    fn events(inp: &[u8]) -> (Vec<String>, Result<Flow>) {
+        let mut recorder = Recorder::default();
+        let res = decode_with_visitor(inp, &mut recorder);
+        (recorder.events, res)
+    }
+
+    #[test]
+    fn visits_values_in_order() {
+        let (events, res) = events(b"d1:ali1e1:ad0:i-2eee1:b2:xye");
+        assert_eq!(Ok(Flow::Continue(())), res);
+        let expected = ["{", "key a", "[", "int 1", "str a", "{", "key ", "int -2", "}", "]", "key b", "str xy", "}"];
+        assert_eq!(expected.to_vec(), events);
+    }
+
+    #[test]
+    fn reports_errors_after_visiting_prefix() {
+        let (events, res) = events(b"d1:ali1ei");
+        assert_eq!(vec!["{", "key a", "[", "int 1"], events);
+        assert_eq!(Err(crate::bdecode::decode(b"d1:ali1ei").unwrap_err()), res);
+    }
+
+    #[test]
+    fn stops_early_on_break() {
+        let mut visitor = Announce::default();
+        let res = decode_with_visitor(b"d4:infod8:announce1:xe8:announce3:url6:pieces1:xe", &mut visitor);
+        assert_eq!(Ok(Flow::Break(())), res);
+        assert_eq!(Some(b"url".as_slice()), visitor.announce);
+
+        // Nothing after the break is validated
+        let mut visitor = Announce::default();
+        assert_eq!(Ok(Flow::Break(())), decode_with_visitor(b"d8:announce3:url6:piecesi", &mut visitor));
+        assert_eq!(Some(b"url".as_slice()), visitor.announce);
+
+        // Without the key the visit runs to the end
+        let mut visitor = Announce::default();
+        assert_eq!(Ok(Flow::Continue(())), decode_with_visitor(b"d4:infod8:announce1:xee", &mut visitor));
+        assert_eq!(None, visitor.announce);
+    }
+}